@@ -0,0 +1,134 @@
+use std::fmt;
+use std::fs::File;
+use std::io as stdio;
+use std::io::Read;
+use std::path::Path;
+use std::str::FromStr;
+use crc32fast;
+use md5;
+use sha1_smol;
+use sha2::{Sha256, Digest};
+
+/// Hash algorithms understood by the HASH command and the XCRC/XMD5/XSHA* extensions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    Crc32,
+    Md5,
+    Sha1,
+    Sha256,
+}
+
+impl Algorithm {
+    /// The name used by the HASH command, as listed in FEAT
+    pub fn hash_name(&self) -> &'static str {
+        match *self {
+            Algorithm::Crc32 => "CRC32",
+            Algorithm::Md5 => "MD5",
+            Algorithm::Sha1 => "SHA-1",
+            Algorithm::Sha256 => "SHA-256",
+        }
+    }
+
+    /// The non standard command computing this hash
+    pub fn x_command(&self) -> &'static str {
+        match *self {
+            Algorithm::Crc32 => "XCRC",
+            Algorithm::Md5 => "XMD5",
+            Algorithm::Sha1 => "XSHA1",
+            Algorithm::Sha256 => "XSHA256",
+        }
+    }
+}
+
+impl fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.hash_name())
+    }
+}
+
+impl FromStr for Algorithm {
+    type Err = String;
+
+    /// Accepts the HASH names as well as the lowercase forms without dash, e.g. `sha256`
+    fn from_str(s: &str) -> ::std::result::Result<Algorithm, String> {
+        match s.to_uppercase().replace("-", "").as_ref() {
+            "CRC32" | "CRC" => Ok(Algorithm::Crc32),
+            "MD5" => Ok(Algorithm::Md5),
+            "SHA1" => Ok(Algorithm::Sha1),
+            "SHA256" => Ok(Algorithm::Sha256),
+            _ => Err(format!("unknown hash algorithm {}", s)),
+        }
+    }
+}
+
+/// A checksum reported by the server
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Checksum {
+    pub algorithm: Algorithm,
+    /// Lowercase hex digest
+    pub value: String,
+}
+
+enum State {
+    Crc32(crc32fast::Hasher),
+    Md5(md5::Context),
+    Sha1(sha1_smol::Sha1),
+    Sha256(Sha256),
+}
+
+/// Computes a checksum incrementally
+pub struct Hasher {
+    state: State,
+}
+
+impl Hasher {
+    pub fn new(algorithm: Algorithm) -> Hasher {
+        let state = match algorithm {
+            Algorithm::Crc32 => State::Crc32(crc32fast::Hasher::new()),
+            Algorithm::Md5 => State::Md5(md5::Context::new()),
+            Algorithm::Sha1 => State::Sha1(sha1_smol::Sha1::new()),
+            Algorithm::Sha256 => State::Sha256(Sha256::new()),
+        };
+        Hasher { state: state }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        match self.state {
+            State::Crc32(ref mut h) => h.update(data),
+            State::Md5(ref mut h) => h.consume(data),
+            State::Sha1(ref mut h) => h.update(data),
+            State::Sha256(ref mut h) => h.update(data),
+        }
+    }
+
+    /// Returns the lowercase hex digest
+    pub fn finish(self) -> String {
+        match self.state {
+            State::Crc32(h) => format!("{:08x}", h.finalize()),
+            State::Md5(h) => to_hex(&h.compute()[..]),
+            State::Sha1(h) => h.digest().to_string(),
+            State::Sha256(h) => to_hex(&h.finalize()[..]),
+        }
+    }
+}
+
+/// Computes the checksum of a local file
+pub fn file_checksum<P: AsRef<Path>>(path: P, algorithm: Algorithm) -> stdio::Result<String> {
+    let mut file = try!(File::open(path));
+    let mut hasher = Hasher::new(algorithm);
+    let mut buf = [0; 8192];
+    loop {
+        match try!(file.read(&mut buf)) {
+            0 => break,
+            n => hasher.update(&buf[..n]),
+        }
+    }
+    Ok(hasher.finish())
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,33 @@
+use std::env;
+use std::fs;
+use super::{Algorithm, Hasher, file_checksum};
+
+#[test]
+fn digests() {
+    let digests = [
+        (Algorithm::Crc32, "352441c2"),
+        (Algorithm::Md5, "900150983cd24fb0d6963f7d28e17f72"),
+        (Algorithm::Sha1, "a9993e364706816aba3e25717850c26c9cd0d89d"),
+        (Algorithm::Sha256, "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"),
+    ];
+    let path = env::temp_dir().join(format!("zftp-checksum-{}", ::std::process::id()));
+    fs::write(&path, b"abc").unwrap();
+    for &(algorithm, digest) in &digests {
+        let mut hasher = Hasher::new(algorithm);
+        hasher.update(b"a");
+        hasher.update(b"bc");
+        assert_eq!(hasher.finish(), digest);
+        assert_eq!(file_checksum(&path, algorithm).unwrap(), digest);
+    }
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn names() {
+    assert_eq!("SHA-256".parse(), Ok(Algorithm::Sha256));
+    assert_eq!("sha256".parse(), Ok(Algorithm::Sha256));
+    assert_eq!("crc".parse(), Ok(Algorithm::Crc32));
+    assert!("sha512".parse::<Algorithm>().is_err());
+    assert_eq!(Algorithm::Sha1.to_string(), "SHA-1");
+    assert_eq!(Algorithm::Sha1.x_command(), "XSHA1");
+}
@@ -0,0 +1,192 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::File;
+use std::io as stdio;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+use toml::Value;
+use toml::value::Table;
+use super::throttle::parse_rate;
+
+/// The settings of a host, every one of them is optional
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Profile {
+    pub host: Option<String>,
+    pub port: Option<u16>,
+    pub user: Option<String>,
+    pub password: Option<String>,
+    pub account: Option<String>,
+    pub anonymous: Option<bool>,
+    /// The directory to change to after logging in
+    pub dir: Option<String>,
+    pub passive: Option<bool>,
+    pub timeout: Option<Duration>,
+    pub compress: Option<bool>,
+    /// The number of connections of batch transfers
+    pub parallel: Option<usize>,
+    /// Bytes per second, 0 means unlimited
+    pub rate: Option<u64>,
+}
+
+impl Profile {
+    /// Fills the settings missing from this profile with those of `other`
+    pub fn or(self, other: &Profile) -> Profile {
+        Profile {
+            host: self.host.or(other.host.clone()),
+            port: self.port.or(other.port),
+            user: self.user.or(other.user.clone()),
+            password: self.password.or(other.password.clone()),
+            account: self.account.or(other.account.clone()),
+            anonymous: self.anonymous.or(other.anonymous),
+            dir: self.dir.or(other.dir.clone()),
+            passive: self.passive.or(other.passive),
+            timeout: self.timeout.or(other.timeout),
+            compress: self.compress.or(other.compress),
+            parallel: self.parallel.or(other.parallel),
+            rate: self.rate.or(other.rate),
+        }
+    }
+
+    fn from_table(section: &str, table: &Table) -> ::std::result::Result<Profile, String> {
+        let mut profile = Profile::default();
+        for (key, value) in table {
+            let setting = format!("{}.{}", section, key);
+            match &key[..] {
+                "host" => profile.host = Some(try!(string(&setting, value))),
+                "port" => {
+                    let port = try!(integer(&setting, value));
+                    if port < 1 || port > 65535 {
+                        return Err(format!("{}: invalid port {}", setting, port));
+                    }
+                    profile.port = Some(port as u16);
+                }
+                "user" => profile.user = Some(try!(string(&setting, value))),
+                "password" => profile.password = Some(try!(string(&setting, value))),
+                "account" => profile.account = Some(try!(string(&setting, value))),
+                "anonymous" => profile.anonymous = Some(try!(boolean(&setting, value))),
+                "dir" => profile.dir = Some(try!(string(&setting, value))),
+                "passive" => profile.passive = Some(try!(boolean(&setting, value))),
+                // only what zftp does anyway is accepted, so that a profile asking for more fails
+                "tls" => match &try!(string(&setting, value)).to_lowercase()[..] {
+                    "none" => (),
+                    tls => return Err(format!("{}: TLS ({}) is not supported, only \"none\" is", setting, tls)),
+                },
+                "charset" => match &try!(string(&setting, value)).to_lowercase()[..] {
+                    "utf-8" | "utf8" => (),
+                    charset => return Err(format!("{}: charset {} is not supported, only \"utf-8\" is", setting, charset)),
+                },
+                "timeout" => {
+                    let timeout = try!(integer(&setting, value));
+                    if timeout == 0 {
+                        return Err(format!("{}: leave it out to wait forever", setting));
+                    }
+                    profile.timeout = Some(Duration::from_secs(timeout));
+                }
+                "compress" => profile.compress = Some(try!(boolean(&setting, value))),
+                "parallel" => {
+                    let parallel = try!(integer(&setting, value));
+                    if parallel == 0 {
+                        return Err(format!("{}: at least one connection is needed", setting));
+                    }
+                    profile.parallel = Some(parallel as usize);
+                }
+                "rate" => {
+                    // either bytes per second or a string like "500K"
+                    let rate = match *value {
+                        Value::String(ref rate) => parse_rate(rate),
+                        _ => Some(try!(integer(&setting, value))),
+                    };
+                    profile.rate = Some(try!(rate.ok_or(format!("{}: invalid rate", setting))));
+                }
+                _ => return Err(format!("{}: unknown setting", setting)),
+            }
+        }
+        Ok(profile)
+    }
+}
+
+fn string(setting: &str, value: &Value) -> ::std::result::Result<String, String> {
+    value.as_str().map(String::from).ok_or(format!("{}: expected a string, got {}", setting, value.type_str()))
+}
+
+fn integer(setting: &str, value: &Value) -> ::std::result::Result<u64, String> {
+    match value.as_integer() {
+        Some(n) if n >= 0 => Ok(n as u64),
+        _ => Err(format!("{}: expected a positive integer, got {}", setting, value)),
+    }
+}
+
+fn boolean(setting: &str, value: &Value) -> ::std::result::Result<bool, String> {
+    value.as_bool().ok_or(format!("{}: expected true or false, got {}", setting, value.type_str()))
+}
+
+/// The `zftp` configuration file: settings applying to every host under `[defaults]`, and
+/// named host profiles under `[profiles.<name>]`
+///
+/// ```toml
+/// [defaults]
+/// timeout = 30
+///
+/// [profiles.prod-sftp-drop]
+/// host = "drop.example.com"
+/// user = "deploy"
+/// passive = false
+/// dir = "/incoming"
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Config {
+    pub defaults: Profile,
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+impl Config {
+    /// `zftp/config.toml` in `$XDG_CONFIG_HOME`, or in `.config` in the home directory
+    pub fn default_path() -> Option<PathBuf> {
+        env::var_os("XDG_CONFIG_HOME").map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+            .map(|dir| dir.join("zftp").join("config.toml"))
+    }
+
+    /// Reads and parses a configuration file, a malformed file yields an `InvalidData` error
+    pub fn load<P: AsRef<Path>>(path: P) -> stdio::Result<Config> {
+        let mut text = String::new();
+        try!(File::open(path).and_then(|mut file| file.read_to_string(&mut text)));
+        text.parse().map_err(|err| stdio::Error::new(stdio::ErrorKind::InvalidData, err))
+    }
+
+    /// The profile `name` completed with the defaults
+    pub fn profile(&self, name: &str) -> Option<Profile> {
+        self.profiles.get(name).map(|profile| profile.clone().or(&self.defaults))
+    }
+}
+
+impl FromStr for Config {
+    type Err = String;
+
+    fn from_str(s: &str) -> ::std::result::Result<Config, String> {
+        let root: Value = try!(s.parse().map_err(|err| format!("{}", err)));
+        let root = try!(root.as_table().ok_or(String::from("expected a table")));
+
+        let mut config = Config::default();
+        for (key, value) in root {
+            let table = try!(value.as_table().ok_or(format!("{}: expected a table", key)));
+            match &key[..] {
+                "defaults" => config.defaults = try!(Profile::from_table(key, table)),
+                "profiles" => {
+                    for (name, profile) in table {
+                        let section = format!("profiles.{}", name);
+                        let profile = try!(profile.as_table().ok_or(format!("{}: expected a table", section)));
+                        config.profiles.insert(name.clone(), try!(Profile::from_table(&section, profile)));
+                    }
+                }
+                _ => return Err(format!("{}: unknown section", key)),
+            }
+        }
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests;
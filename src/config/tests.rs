@@ -0,0 +1,66 @@
+use std::time::Duration;
+use super::Config;
+
+const CONFIG: &'static str = r#"
+[defaults]
+timeout = 30
+passive = true
+rate = "500K"
+tls = "none"
+charset = "UTF-8"
+
+[profiles.prod-sftp-drop]
+host = "drop.example.com"
+port = 2121
+user = "deploy"
+passive = false
+dir = "/incoming"
+parallel = 2
+
+[profiles.mirror]
+host = "mirror.example.org"
+anonymous = true
+rate = 0
+"#;
+
+#[test]
+fn profiles() {
+    let config: Config = CONFIG.parse().unwrap();
+    assert_eq!(config.profiles.keys().collect::<Vec<_>>(), vec!["mirror", "prod-sftp-drop"]);
+
+    let drop = config.profile("prod-sftp-drop").unwrap();
+    assert_eq!(drop.host, Some(String::from("drop.example.com")));
+    assert_eq!(drop.port, Some(2121));
+    assert_eq!(drop.user, Some(String::from("deploy")));
+    assert_eq!(drop.passive, Some(false));
+    assert_eq!(drop.dir, Some(String::from("/incoming")));
+    assert_eq!(drop.parallel, Some(2));
+    // from the defaults
+    assert_eq!(drop.timeout, Some(Duration::from_secs(30)));
+    assert_eq!(drop.rate, Some(500 * 1024));
+
+    let mirror = config.profile("mirror").unwrap();
+    assert_eq!(mirror.anonymous, Some(true));
+    assert_eq!(mirror.passive, Some(true));
+    assert_eq!(mirror.rate, Some(0));
+    assert_eq!(mirror.port, None);
+
+    assert!(config.profile("missing").is_none());
+}
+
+#[test]
+fn invalid() {
+    assert!("[defaults]\nport = 70000".parse::<Config>().is_err());
+    assert!("[defaults]\npassive = \"yes\"".parse::<Config>().is_err());
+    assert!("[defaults]\nrate = \"fast\"".parse::<Config>().is_err());
+    assert!("[defaults]\nparallel = 0".parse::<Config>().is_err());
+    assert!("[defaults]\ntimeout = 0".parse::<Config>().is_err());
+    assert!("[profiles.typo]\nhots = \"example.com\"".parse::<Config>().is_err());
+    assert!("[profile.singular]\nhost = \"example.com\"".parse::<Config>().is_err());
+    assert!("[profiles]\nhost = \"example.com\"".parse::<Config>().is_err());
+    assert!("[defaults".parse::<Config>().is_err());
+    let err = "[profiles.secure]\ntls = \"explicit\"".parse::<Config>().unwrap_err();
+    assert_eq!(err, "profiles.secure.tls: TLS (explicit) is not supported, only \"none\" is");
+    assert!("[defaults]\ncharset = \"latin1\"".parse::<Config>().unwrap_err().contains("not supported"));
+    assert_eq!("".parse::<Config>().unwrap(), Config::default());
+}
@@ -0,0 +1,317 @@
+use std::io as stdio;
+use std::io::{Read, Write, BufRead, BufReader, BufWriter};
+use std::net::TcpStream;
+use std::time::Instant;
+use flate2::Compression;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use super::ftp::FtpStream;
+use super::progress::{Direction, Progress};
+use super::checksum::Hasher;
+use super::status;
+use super::types::{FtpError, Line, Reply, Result};
+
+/// The reading end of a data connection, inflating the data in MODE Z
+pub(crate) enum Reader {
+    Plain(BufReader<TcpStream>),
+    Inflate(BufReader<ZlibDecoder<TcpStream>>),
+}
+
+impl Reader {
+    pub(crate) fn new(data: TcpStream, compressed: bool) -> Reader {
+        if compressed {
+            Reader::Inflate(BufReader::new(ZlibDecoder::new(data)))
+        } else {
+            Reader::Plain(BufReader::new(data))
+        }
+    }
+}
+
+impl Read for Reader {
+    fn read(&mut self, buf: &mut [u8]) -> stdio::Result<usize> {
+        match *self {
+            Reader::Plain(ref mut r) => r.read(buf),
+            Reader::Inflate(ref mut r) => r.read(buf),
+        }
+    }
+}
+
+impl BufRead for Reader {
+    fn fill_buf(&mut self) -> stdio::Result<&[u8]> {
+        match *self {
+            Reader::Plain(ref mut r) => r.fill_buf(),
+            Reader::Inflate(ref mut r) => r.fill_buf(),
+        }
+    }
+
+    fn consume(&mut self, amt: usize) {
+        match *self {
+            Reader::Plain(ref mut r) => r.consume(amt),
+            Reader::Inflate(ref mut r) => r.consume(amt),
+        }
+    }
+}
+
+/// The writing end of a data connection, deflating the data in MODE Z
+enum Writer {
+    Plain(BufWriter<TcpStream>),
+    Deflate(ZlibEncoder<BufWriter<TcpStream>>),
+}
+
+impl Writer {
+    fn new(data: TcpStream, compressed: bool) -> Writer {
+        if compressed {
+            Writer::Deflate(ZlibEncoder::new(BufWriter::new(data), Compression::default()))
+        } else {
+            Writer::Plain(BufWriter::new(data))
+        }
+    }
+
+    /// Writes out everything still buffered, the connection is closed when dropped
+    fn close(self) -> stdio::Result<()> {
+        match self {
+            Writer::Plain(mut w) => w.flush(),
+            Writer::Deflate(w) => w.finish().and_then(|mut w| w.flush()),
+        }
+    }
+}
+
+impl Write for Writer {
+    fn write(&mut self, buf: &[u8]) -> stdio::Result<usize> {
+        match *self {
+            Writer::Plain(ref mut w) => w.write(buf),
+            Writer::Deflate(ref mut w) => w.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> stdio::Result<()> {
+        match *self {
+            Writer::Plain(ref mut w) => w.flush(),
+            Writer::Deflate(ref mut w) => w.flush(),
+        }
+    }
+}
+
+/// Counts and hashes the bytes going through a data connection
+struct Tracker {
+    direction: Direction,
+    bytes: u64,
+    total: Option<u64>,
+    started: Instant,
+    hasher: Option<Hasher>,
+    /// Remote file to verify the checksum against
+    path: Option<String>,
+}
+
+impl Tracker {
+    fn new(ftp: &mut FtpStream, direction: Direction, total: Option<u64>, path: Option<String>) -> Tracker {
+        Tracker {
+            direction: direction,
+            bytes: 0,
+            total: total,
+            started: Instant::now(),
+            hasher: ftp.checksum_hasher(),
+            path: path,
+        }
+    }
+
+    fn progress(&self) -> Progress {
+        Progress {
+            direction: self.direction,
+            bytes: self.bytes,
+            total: self.total,
+            elapsed: self.started.elapsed(),
+        }
+    }
+
+    fn advance(&mut self, ftp: &mut FtpStream, data: &[u8]) {
+        if data.len() > 0 {
+            self.bytes += data.len() as u64;
+            if let Some(ref mut hasher) = self.hasher {
+                hasher.update(data);
+            }
+            ftp.throttle(self.direction, data.len());
+            ftp.notify_progress(&self.progress(), false);
+        }
+    }
+
+    fn finish(&mut self, ftp: &mut FtpStream) -> Result<()> {
+        match self.hasher.take() {
+            Some(hasher) => ftp.transfer_checksum(self.path.as_ref().map(|path| &path[..]), hasher),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A data connection being written to, returned by `FtpStream::put_stream` and
+/// `FtpStream::append_stream`
+///
+/// The transfer is completed by `finish`, which closes the data connection and reads
+/// the server's reply. Dropping the stream does the same but ignores any error.
+pub struct UploadStream<'a> {
+    ftp: &'a mut FtpStream,
+    data: Option<Writer>,
+    tracker: Tracker,
+}
+
+impl<'a> UploadStream<'a> {
+    pub(crate) fn new(ftp: &'a mut FtpStream, data: TcpStream, path: Option<String>) -> UploadStream<'a> {
+        let tracker = Tracker::new(ftp, Direction::Upload, None, path);
+        let data = Writer::new(data, ftp.is_compressed());
+        UploadStream {
+            ftp: ftp,
+            data: Some(data),
+            tracker: tracker,
+        }
+    }
+
+    /// Sets the number of bytes going to be written, reported to the progress observer
+    pub fn set_total(&mut self, total: u64) {
+        self.tracker.total = Some(total);
+    }
+
+    /// Closes the data connection and checks that the server stored the file
+    pub fn finish(mut self) -> Result<()> {
+        self.close().map(|_| ())
+    }
+
+    /// Like `finish`, returning the server's final reply
+    pub(crate) fn finish_response(mut self) -> Result<Line> {
+        self.close().and_then(|line| {
+            line.ok_or(FtpError::InvalidResponse("error: transfer already finished".to_owned()))
+        })
+    }
+
+    fn close(&mut self) -> Result<Option<Line>> {
+        let flushed = match self.data.take() {
+            Some(data) => data.close(),
+            None => return Ok(None),
+        };
+        self.ftp.notify_progress(&self.tracker.progress(), true);
+        // the server only replies once the connection is closed, even if flushing failed
+        let line = try!(read_transfer_response(self.ftp));
+        try!(flushed);
+        try!(self.tracker.finish(self.ftp));
+        Ok(Some(line))
+    }
+}
+
+impl<'a> Write for UploadStream<'a> {
+    fn write(&mut self, buf: &[u8]) -> stdio::Result<usize> {
+        let len = self.ftp.chunk(Direction::Upload, buf.len());
+        let n = match self.data {
+            Some(ref mut data) => try!(data.write(&buf[..len])),
+            None => return Err(stdio::Error::new(stdio::ErrorKind::NotConnected, "data connection closed")),
+        };
+        self.tracker.advance(self.ftp, &buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> stdio::Result<()> {
+        match self.data {
+            Some(ref mut data) => data.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<'a> Drop for UploadStream<'a> {
+    fn drop(&mut self) {
+        let _ = self.close();
+    }
+}
+
+/// A data connection being read from, returned by `FtpStream::get`
+///
+/// The transfer is completed by `finish`, which closes the data connection and reads
+/// the server's reply. Dropping the stream does the same but ignores any error.
+/// Finishing before the end of the file aborts the transfer and usually makes the
+/// server reply with 426.
+pub struct DownloadStream<'a> {
+    ftp: &'a mut FtpStream,
+    data: Option<Reader>,
+    tracker: Tracker,
+}
+
+impl<'a> DownloadStream<'a> {
+    pub(crate) fn new(ftp: &'a mut FtpStream, data: TcpStream, total: Option<u64>,
+                      path: Option<String>) -> DownloadStream<'a> {
+        let tracker = Tracker::new(ftp, Direction::Download, total, path);
+        let data = Reader::new(data, ftp.is_compressed());
+        DownloadStream {
+            ftp: ftp,
+            data: Some(data),
+            tracker: tracker,
+        }
+    }
+
+    /// The size of the file announced by the server, if any
+    pub fn total(&self) -> Option<u64> {
+        self.tracker.total
+    }
+
+    /// Closes the data connection and checks that the server completed the transfer
+    pub fn finish(mut self) -> Result<()> {
+        self.close()
+    }
+
+    fn close(&mut self) -> Result<()> {
+        match self.data.take() {
+            Some(data) => drop(data),
+            None => return Ok(()),
+        }
+        self.ftp.notify_progress(&self.tracker.progress(), true);
+        try!(read_transfer_response(self.ftp));
+        self.tracker.finish(self.ftp)
+    }
+}
+
+impl<'a> Read for DownloadStream<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> stdio::Result<usize> {
+        let len = self.ftp.chunk(Direction::Download, buf.len());
+        let n = match self.data {
+            Some(ref mut data) => try!(data.read(&mut buf[..len])),
+            None => return Ok(0),
+        };
+        self.tracker.advance(self.ftp, &buf[..n]);
+        Ok(n)
+    }
+}
+
+impl<'a> BufRead for DownloadStream<'a> {
+    fn fill_buf(&mut self) -> stdio::Result<&[u8]> {
+        match self.data {
+            Some(ref mut data) => data.fill_buf(),
+            None => Ok(&[]),
+        }
+    }
+
+    fn consume(&mut self, amt: usize) {
+        if let Some(ref mut data) = self.data {
+            // the consumed bytes are still buffered, filling doesn't read again
+            if let Ok(buf) = data.fill_buf() {
+                let amt = if amt < buf.len() { amt } else { buf.len() };
+                self.tracker.advance(self.ftp, &buf[..amt]);
+            }
+            data.consume(amt);
+        }
+    }
+}
+
+impl<'a> Drop for DownloadStream<'a> {
+    fn drop(&mut self) {
+        let _ = self.close();
+    }
+}
+
+/// Reads the reply closing a transfer, a 426 or 451 is reported as `FtpError::TransferAborted`
+fn read_transfer_response(ftp: &mut FtpStream) -> Result<Line> {
+    let Reply(code, mut lines) = try!(ftp.read_reply());
+    let line = Line(code, lines.pop().unwrap());
+    match code {
+        status::CLOSING_DATA_CONNECTION | status::REQUESTED_FILE_ACTION_OK => Ok(line),
+        status::TRANSER_ABORTED | status::ACTION_ABORTED => Err(FtpError::TransferAborted(line)),
+        _ => Err(FtpError::InvalidResponse(format!("Expected codes {:?} got reponse: {:?}",
+                                                   [status::CLOSING_DATA_CONNECTION, status::REQUESTED_FILE_ACTION_OK], line.1))),
+    }
+}
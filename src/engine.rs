@@ -0,0 +1,263 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write, copy};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+use super::ftp::FtpStream;
+use super::progress::Progress;
+use super::throttle::RateLimiter;
+use super::mtime::{local_mtime, set_local_mtime};
+use super::types::{FtpError, Result};
+
+/// A single file transfer run by the `TransferEngine`
+#[derive(Debug, Clone)]
+pub enum Job {
+    /// Download the remote file `remote` into the local file `local`
+    Get { remote: String, local: String },
+    /// Upload the local file `local` to the remote file `remote`
+    Put { local: String, remote: String },
+}
+
+impl Job {
+    pub fn get(remote: &str, local: &str) -> Job {
+        Job::Get { remote: remote.to_string(), local: local.to_string() }
+    }
+
+    pub fn put(local: &str, remote: &str) -> Job {
+        Job::Put { local: local.to_string(), remote: remote.to_string() }
+    }
+
+    /// The remote path the job reads from or writes to
+    pub fn remote(&self) -> &str {
+        match *self {
+            Job::Get { ref remote, .. } | Job::Put { ref remote, .. } => remote,
+        }
+    }
+
+    /// The local path the job reads from or writes to
+    pub fn local(&self) -> &str {
+        match *self {
+            Job::Get { ref local, .. } | Job::Put { ref local, .. } => local,
+        }
+    }
+}
+
+/// The outcome of a `Job`, `index` is the position of the job in the submitted list
+#[derive(Debug)]
+pub struct JobResult {
+    pub index: usize,
+    pub job: Job,
+    pub bytes: u64,
+    pub elapsed: Duration,
+    pub result: Result<()>,
+}
+
+/// Notifications sent to the observer while a batch is running
+#[derive(Debug)]
+pub enum Event<'a> {
+    /// `worker` picked up the job at `index`
+    Started { index: usize, worker: usize, job: &'a Job },
+    /// Data of the job at `index` went through the data connection
+    Progress { index: usize, worker: usize, progress: &'a Progress },
+    /// A job has completed, `done` of `total` jobs are finished so far
+    Finished { result: &'a JobResult, done: usize, total: usize },
+}
+
+type Connector = dyn Fn() -> Result<FtpStream> + Send + Sync;
+type Observer = dyn Fn(&Event) + Send + Sync;
+
+/// Runs a list of jobs concurrently, each worker owning its own `FtpStream`
+///
+/// Connections are opened lazily through the connector, so it should also log in
+/// and change to the right directory before returning the stream.
+pub struct TransferEngine {
+    connector: Arc<Connector>,
+    observer: Option<Arc<Observer>>,
+    workers: usize,
+    download_limit: Option<RateLimiter>,
+    upload_limit: Option<RateLimiter>,
+    preserve: bool,
+}
+
+impl TransferEngine {
+    pub fn new<C>(connector: C) -> TransferEngine
+        where C: Fn() -> Result<FtpStream> + Send + Sync + 'static {
+        TransferEngine {
+            connector: Arc::new(connector),
+            observer: None,
+            workers: 1,
+            download_limit: None,
+            upload_limit: None,
+            preserve: true,
+        }
+    }
+
+    /// Sets the number of concurrent connections, at least one is always used
+    pub fn workers(mut self, workers: usize) -> TransferEngine {
+        self.workers = if workers == 0 { 1 } else { workers };
+        self
+    }
+
+    /// Limits the bandwidth of the batch, the budget is shared by all workers
+    pub fn limit(mut self, download: Option<RateLimiter>, upload: Option<RateLimiter>) -> TransferEngine {
+        self.download_limit = download;
+        self.upload_limit = upload;
+        self
+    }
+
+    /// Gives transferred files the modification time of their source, on a best effort basis
+    ///
+    /// This is on by default, so batches behave like a mirror of the source files.
+    pub fn preserve(mut self, preserve: bool) -> TransferEngine {
+        self.preserve = preserve;
+        self
+    }
+
+    /// Sets a callback receiving the progress of the batch
+    pub fn observe<O>(mut self, observer: O) -> TransferEngine
+        where O: Fn(&Event) + Send + Sync + 'static {
+        self.observer = Some(Arc::new(observer));
+        self
+    }
+
+    /// Runs all jobs and returns their results ordered as submitted
+    pub fn run(&self, jobs: Vec<Job>) -> Vec<JobResult> {
+        let total = jobs.len();
+        let queue: Arc<Mutex<VecDeque<(usize, Job)>>> =
+            Arc::new(Mutex::new(jobs.into_iter().enumerate().collect()));
+        let results: Arc<Mutex<Vec<JobResult>>> = Arc::new(Mutex::new(Vec::with_capacity(total)));
+        let done = Arc::new(AtomicUsize::new(0));
+
+        let workers = if self.workers < total { self.workers } else { total };
+        let handles: Vec<_> = (0..workers).map(|worker| {
+            let queue = queue.clone();
+            let results = results.clone();
+            let done = done.clone();
+            let connector = self.connector.clone();
+            let observer = self.observer.clone();
+            let download_limit = self.download_limit.clone();
+            let upload_limit = self.upload_limit.clone();
+            let preserve = self.preserve;
+
+            thread::spawn(move || {
+                let mut stream: Option<FtpStream> = None;
+                loop {
+                    let next = queue.lock().unwrap().pop_front();
+                    let (index, job) = match next {
+                        Some(next) => next,
+                        None => break,
+                    };
+                    if let Some(ref observer) = observer {
+                        observer(&Event::Started { index: index, worker: worker, job: &job });
+                    }
+
+                    let start = Instant::now();
+                    let outcome = match stream.take() {
+                        Some(s) => Ok(s),
+                        None => connector().map(|mut s| {
+                            s.set_verbose(false);
+                            s.set_download_limit(download_limit.clone());
+                            s.set_upload_limit(upload_limit.clone());
+                            s
+                        }),
+                    }.and_then(|mut s| {
+                        if let Some(ref observer) = observer {
+                            let observer = observer.clone();
+                            s.set_progress(move |progress: &Progress| {
+                                observer(&Event::Progress { index: index, worker: worker, progress: progress });
+                            });
+                        }
+                        let outcome = run_job(&mut s, &job, preserve);
+                        s.clear_progress();
+                        // a broken control connection is reopened for the next job
+                        match outcome {
+                            Err(FtpError::ConnectionError(_)) => (),
+                            _ => stream = Some(s),
+                        }
+                        outcome
+                    });
+
+                    let (bytes, result) = match outcome {
+                        Ok(bytes) => (bytes, Ok(())),
+                        Err(err) => (0, Err(err)),
+                    };
+                    let job_result = JobResult {
+                        index: index,
+                        job: job,
+                        bytes: bytes,
+                        elapsed: start.elapsed(),
+                        result: result,
+                    };
+                    let finished = done.fetch_add(1, Ordering::SeqCst) + 1;
+                    if let Some(ref observer) = observer {
+                        observer(&Event::Finished { result: &job_result, done: finished, total: total });
+                    }
+                    results.lock().unwrap().push(job_result);
+                }
+                if let Some(mut s) = stream {
+                    let _ = s.quit();
+                }
+            })
+        }).collect();
+
+        for handle in handles {
+            let _ = handle.join();
+        }
+
+        let mut results = match Arc::try_unwrap(results) {
+            Ok(results) => results.into_inner().unwrap(),
+            Err(_) => unreachable!(),
+        };
+        // jobs left behind by a panicked worker are still reported
+        for (index, job) in queue.lock().unwrap().drain(..) {
+            results.push(JobResult {
+                index: index,
+                job: job,
+                bytes: 0,
+                elapsed: Duration::from_secs(0),
+                result: Err(FtpError::InvalidResponse("job was not run".to_owned())),
+            });
+        }
+        results.sort_by_key(|r| r.index);
+        results
+    }
+}
+
+fn run_job(stream: &mut FtpStream, job: &Job, preserve: bool) -> Result<u64> {
+    match *job {
+        Job::Get { ref remote, ref local } => {
+            let mut data = try!(stream.get(remote));
+            let mut file = BufWriter::new(try!(File::create(local).map_err(FtpError::LocalError)));
+            let copied = copy(&mut data, &mut file);
+            try!(data.finish());
+            let bytes = try!(copied.map_err(FtpError::LocalError));
+            try!(file.flush().map_err(FtpError::LocalError));
+            if preserve {
+                if let Ok(Some(mtime)) = stream.mdtm(remote) {
+                    let _ = set_local_mtime(local, &mtime);
+                }
+            }
+            Ok(bytes)
+        }
+        Job::Put { ref local, ref remote } => {
+            let mut file = BufReader::new(try!(File::open(local).map_err(FtpError::LocalError)));
+            let bytes = try!(file.get_ref().metadata().map_err(FtpError::LocalError)).len();
+            let mut data = try!(stream.put_stream(remote));
+            data.set_total(bytes);
+            let copied = copy(&mut file, &mut data);
+            try!(data.finish());
+            try!(copied.map_err(FtpError::LocalError));
+            if preserve {
+                if let Ok(mtime) = local_mtime(local) {
+                    let _ = stream.set_mdtm(remote, &mtime);
+                }
+            }
+            Ok(bytes)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
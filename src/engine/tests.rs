@@ -0,0 +1,131 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use super::{TransferEngine, Job, Event};
+use super::super::mock::{MockServer, USER, PASSWORD};
+use super::super::types::FtpError;
+
+/// A fresh local directory under the system's temporary directory
+fn scratch(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("zftp-engine-{}-{}", name, ::std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn engine(server: &MockServer) -> TransferEngine {
+    let addr = server.addr();
+    TransferEngine::new(move || {
+        let mut ftp = try!(::FtpStream::connect_quiet(addr));
+        try!(ftp.login(USER, PASSWORD));
+        Ok(ftp)
+    })
+}
+
+fn connections(server: &MockServer) -> usize {
+    server.received().iter().filter(|command| command.starts_with("USER")).count()
+}
+
+#[test]
+fn get_and_put() {
+    let dir = scratch("batch");
+    let server = MockServer::start();
+    for i in 0..6 {
+        server.fs().add_file(&format!("in/{}.txt", i), format!("file {}", i).as_bytes());
+    }
+    server.fs().add_dir("out");
+
+    let jobs: Vec<Job> = (0..6)
+        .map(|i| Job::get(&format!("in/{}.txt", i), dir.join(format!("{}.txt", i)).to_str().unwrap()))
+        .collect();
+    let results = engine(&server).workers(3).run(jobs);
+    assert_eq!(results.iter().map(|r| r.index).collect::<Vec<_>>(), vec![0, 1, 2, 3, 4, 5]);
+    for result in &results {
+        assert!(result.result.is_ok(), "{:?}", result);
+        assert_eq!(result.bytes, 6);
+    }
+    assert_eq!(fs::read(dir.join("4.txt")).unwrap(), b"file 4");
+    assert!(connections(&server) <= 3);
+
+    let jobs = vec![Job::put(dir.join("1.txt").to_str().unwrap(), "out/1.txt")];
+    let results = engine(&server).run(jobs);
+    assert!(results[0].result.is_ok());
+    assert_eq!(server.fs().contents("out/1.txt").unwrap(), b"file 1");
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn failed_jobs_keep_the_connection() {
+    let dir = scratch("failures");
+    let server = MockServer::start();
+    server.fs().add_file("a.txt", b"a").add_file("b.txt", b"b");
+
+    let jobs = vec![
+        Job::get("missing.txt", dir.join("missing.txt").to_str().unwrap()),
+        // the local directory doesn't exist
+        Job::get("a.txt", dir.join("nowhere/a.txt").to_str().unwrap()),
+        Job::put(dir.join("nothing.txt").to_str().unwrap(), "nothing.txt"),
+        Job::get("b.txt", dir.join("b.txt").to_str().unwrap()),
+    ];
+    let results = engine(&server).run(jobs);
+    match results[0].result {
+        Err(FtpError::InvalidResponse(_)) => (),
+        ref other => panic!("unexpected {:?}", other),
+    }
+    match results[1].result {
+        Err(FtpError::LocalError(_)) => (),
+        ref other => panic!("unexpected {:?}", other),
+    }
+    match results[2].result {
+        Err(FtpError::LocalError(_)) => (),
+        ref other => panic!("unexpected {:?}", other),
+    }
+    assert!(results[3].result.is_ok());
+    assert_eq!(fs::read(dir.join("b.txt")).unwrap(), b"b");
+    assert_eq!(connections(&server), 1);
+    assert_eq!(server.received().last().unwrap(), "QUIT");
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn reconnects_after_losing_the_control_connection() {
+    let dir = scratch("reconnect");
+    let server = MockServer::start();
+    server.fs().add_file("a.txt", b"a").add_file("b.txt", b"b");
+    server.disconnect("RETR");
+
+    let jobs = vec![
+        Job::get("a.txt", dir.join("a.txt").to_str().unwrap()),
+        Job::get("b.txt", dir.join("b.txt").to_str().unwrap()),
+    ];
+    let results = engine(&server).preserve(false).run(jobs);
+    assert!(results[0].result.is_err());
+    assert!(results[1].result.is_ok());
+    assert_eq!(connections(&server), 2);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn observer() {
+    let dir = scratch("observer");
+    let server = MockServer::start();
+    server.fs().add_file("a.txt", b"a").add_file("b.txt", b"b");
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let seen = events.clone();
+    let jobs = vec![
+        Job::get("a.txt", dir.join("a.txt").to_str().unwrap()),
+        Job::get("b.txt", dir.join("b.txt").to_str().unwrap()),
+    ];
+    engine(&server).observe(move |event: &Event| {
+        let name = match *event {
+            Event::Started { index, .. } => format!("started {}", index),
+            Event::Progress { .. } => return,
+            Event::Finished { done, total, .. } => format!("finished {}/{}", done, total),
+        };
+        seen.lock().unwrap().push(name);
+    }).run(jobs);
+    assert_eq!(*events.lock().unwrap(), vec!["started 0", "finished 1/2", "started 1", "finished 2/2"]);
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,230 @@
+use std::fs;
+use std::io as stdio;
+use super::walk::join;
+
+/// A shell style pattern matched against `/` separated paths
+///
+/// `*` matches any run of characters but `/`, `**` any run including `/`, `?` one character
+/// but `/`, and `[a-z]` one character of a set, negated with `[!a-z]`. Patterns without a
+/// `/` are matched against the last component of a path, so `*.txt` matches `docs/a.txt`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pattern {
+    tokens: Vec<Token>,
+    basename: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Char(char),
+    Any,
+    Star,
+    /// `**`, or `**/` when it only matches whole directories
+    DoubleStar(bool),
+    Class(bool, Vec<(char, char)>),
+}
+
+impl Pattern {
+    /// Parses `pattern`, an unclosed `[` is taken literally
+    pub fn new(pattern: &str) -> Pattern {
+        let chars: Vec<char> = pattern.chars().collect();
+        let mut tokens = Vec::new();
+        let mut i = 0;
+        while i < chars.len() {
+            match chars[i] {
+                '*' if chars.get(i + 1) == Some(&'*') => {
+                    i += 2;
+                    // `**/` also matches no directory at all
+                    let dirs = chars.get(i) == Some(&'/');
+                    if dirs {
+                        i += 1;
+                    }
+                    tokens.push(Token::DoubleStar(dirs));
+                    continue;
+                }
+                '*' => tokens.push(Token::Star),
+                '?' => tokens.push(Token::Any),
+                '[' => {
+                    if let Some((class, next)) = parse_class(&chars, i + 1) {
+                        tokens.push(class);
+                        i = next;
+                        continue;
+                    }
+                    tokens.push(Token::Char('['));
+                }
+                c => tokens.push(Token::Char(c)),
+            }
+            i += 1;
+        }
+
+        Pattern {
+            tokens: tokens,
+            basename: !pattern.contains('/'),
+        }
+    }
+
+    /// Whether `path` matches, see `Pattern` for what part of the path is looked at
+    pub fn matches(&self, path: &str) -> bool {
+        let path = path.trim_start_matches("./");
+        let subject = if self.basename {
+            path.trim_end_matches('/').rsplit('/').next().unwrap_or(path)
+        } else {
+            path
+        };
+        let chars: Vec<char> = subject.chars().collect();
+        match_tokens(&self.tokens, &chars)
+    }
+}
+
+/// Whether `s` contains any of the characters special to a `Pattern`
+pub fn is_pattern(s: &str) -> bool {
+    s.contains('*') || s.contains('?') || s.contains('[')
+}
+
+/// Expands a pattern against the local file system, like `FtpStream::glob` does remotely
+///
+/// The last component only matches files unless the pattern ends with `/`. The paths are
+/// sorted, an empty list means nothing matched.
+pub fn glob_local(pattern: &str) -> stdio::Result<Vec<String>> {
+    let dirs_only = pattern.ends_with('/');
+    let parts: Vec<&str> = pattern.split('/').filter(|part| part.len() > 0).collect();
+    let mut matches = vec![if pattern.starts_with('/') { "/".to_string() } else { String::new() }];
+
+    for (i, part) in parts.iter().enumerate() {
+        let last = i + 1 == parts.len();
+        let mut next = Vec::new();
+        for dir in matches {
+            if !is_pattern(part) {
+                next.push(join(&dir, part));
+                continue;
+            }
+            let pattern = Pattern::new(part);
+            let listed = match fs::read_dir(if dir.is_empty() { "." } else { &dir }) {
+                Ok(listed) => listed,
+                Err(_) => continue,
+            };
+            for entry in listed {
+                let entry = try!(entry);
+                let name = entry.file_name().to_string_lossy().into_owned();
+                // like shells, hidden files need a pattern starting with a dot
+                if name.starts_with('.') && !part.starts_with('.') {
+                    continue;
+                }
+                let is_dir = entry.path().is_dir();
+                let wanted = if last && !dirs_only { !is_dir } else { is_dir };
+                if wanted && pattern.matches(&name) {
+                    next.push(join(&dir, &name));
+                }
+            }
+        }
+        matches = next;
+    }
+
+    matches.sort();
+    Ok(matches)
+}
+
+/// Include and exclude patterns selecting the files of a recursive transfer
+///
+/// An excluded directory excludes everything under it. When there are include patterns,
+/// a file must also match one of them, directories are always kept.
+#[derive(Debug, Clone, Default)]
+pub struct Filter {
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+}
+
+impl Filter {
+    pub fn new() -> Filter {
+        Filter::default()
+    }
+
+    pub fn include(mut self, pattern: &str) -> Filter {
+        self.include.push(Pattern::new(pattern));
+        self
+    }
+
+    pub fn exclude(mut self, pattern: &str) -> Filter {
+        self.exclude.push(Pattern::new(pattern));
+        self
+    }
+
+    /// Whether the entry at `path`, relative to the transferred directory, is kept
+    pub fn matches(&self, path: &str, is_dir: bool) -> bool {
+        let mut prefix = 0;
+        while let Some(slash) = path[prefix..].find('/') {
+            prefix += slash;
+            if self.excluded(&path[..prefix]) {
+                return false;
+            }
+            prefix += 1;
+        }
+
+        if self.excluded(path) {
+            return false;
+        }
+        is_dir || self.include.is_empty() || self.include.iter().any(|pattern| pattern.matches(path))
+    }
+
+    fn excluded(&self, path: &str) -> bool {
+        self.exclude.iter().any(|pattern| pattern.matches(path))
+    }
+}
+
+/// Parses a `[...]` class starting after the bracket, returning it with the index following it
+fn parse_class(chars: &[char], start: usize) -> Option<(Token, usize)> {
+    let mut i = start;
+    let negated = match chars.get(i) {
+        Some(&'!') | Some(&'^') => {
+            i += 1;
+            true
+        }
+        _ => false,
+    };
+
+    let mut ranges = Vec::new();
+    let first = i;
+    while i < chars.len() {
+        // a `]` right after the bracket is part of the set
+        if chars[i] == ']' && i > first {
+            return Some((Token::Class(negated, ranges), i + 1));
+        }
+        if chars.get(i + 1) == Some(&'-') && i + 2 < chars.len() && chars[i + 2] != ']' {
+            ranges.push((chars[i], chars[i + 2]));
+            i += 3;
+        } else {
+            ranges.push((chars[i], chars[i]));
+            i += 1;
+        }
+    }
+    None
+}
+
+fn match_tokens(tokens: &[Token], chars: &[char]) -> bool {
+    match tokens.first() {
+        None => chars.is_empty(),
+        Some(&Token::DoubleStar(dirs)) => {
+            (0..chars.len() + 1)
+                .filter(|&skip| !dirs || skip == 0 || chars[skip - 1] == '/')
+                .any(|skip| match_tokens(&tokens[1..], &chars[skip..]))
+        }
+        Some(&Token::Star) => {
+            let run = chars.iter().take_while(|c| **c != '/').count();
+            (0..run + 1).any(|skip| match_tokens(&tokens[1..], &chars[skip..]))
+        }
+        Some(token) => {
+            let matched = match (token, chars.first()) {
+                (_, None) => false,
+                (&Token::Char(c), Some(&next)) => c == next,
+                (&Token::Any, Some(&next)) => next != '/',
+                (&Token::Class(negated, ref ranges), Some(&next)) => {
+                    next != '/' && ranges.iter().any(|&(lo, hi)| lo <= next && next <= hi) != negated
+                }
+                _ => false,
+            };
+            matched && match_tokens(&tokens[1..], &chars[1..])
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,60 @@
+use std::env;
+use std::fs;
+use super::{Pattern, Filter, is_pattern, glob_local};
+
+fn matches(pattern: &str, path: &str) -> bool {
+    Pattern::new(pattern).matches(path)
+}
+
+#[test]
+fn patterns() {
+    assert!(matches("*.txt", "notes.txt"));
+    assert!(!matches("*.txt", "notes.txt.bak"));
+    assert!(matches("file?.log", "file1.log"));
+    assert!(matches("file[0-9].log", "file7.log"));
+    assert!(matches("file[!0-9].log", "fileX.log"));
+    assert!(!matches("file[!0-9].log", "file7.log"));
+    // an unclosed bracket is taken literally
+    assert!(matches("[abc", "[abc"));
+
+    // patterns without a slash look at the last component only
+    assert!(matches("*.txt", "docs/a.txt"));
+    assert!(!matches("docs/*.txt", "docs/sub/a.txt"));
+    assert!(matches("docs/**/*.txt", "docs/a.txt"));
+    assert!(matches("docs/**/*.txt", "docs/sub/deep/a.txt"));
+    assert!(!matches("**/target", "crates/cli-target"));
+}
+
+#[test]
+fn filters() {
+    let filter = Filter::new().include("*.txt").exclude("target").exclude("draft*");
+    assert!(filter.matches("docs/notes.txt", false));
+    assert!(!filter.matches("docs/image.png", false));
+    // directories are always kept unless excluded, with everything under them
+    assert!(filter.matches("docs", true));
+    assert!(!filter.matches("target", true));
+    assert!(!filter.matches("crates/target/notes.txt", false));
+    assert!(!filter.matches("draft.txt", false));
+}
+
+#[test]
+fn local() {
+    let dir = env::temp_dir().join(format!("zftp-glob-{}", ::std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("logs")).unwrap();
+    for file in &["a.txt", "b.txt", ".hidden.txt", "logs/1.log"] {
+        fs::write(dir.join(file), b"").unwrap();
+    }
+    let root = dir.to_str().unwrap();
+    let glob = |pattern: &str| -> Vec<String> {
+        glob_local(&format!("{}/{}", root, pattern)).unwrap().iter().map(|path| path[root.len() + 1..].to_string()).collect()
+    };
+
+    assert!(is_pattern("*.txt") && !is_pattern("notes.txt"));
+    // like shells, hidden files need a pattern starting with a dot
+    assert_eq!(glob("*.txt"), vec!["a.txt", "b.txt"]);
+    assert_eq!(glob("*/*.log"), vec!["logs/1.log"]);
+    assert_eq!(glob("*/"), vec!["logs"]);
+    assert!(glob("missing/*").is_empty());
+    fs::remove_dir_all(&dir).unwrap();
+}
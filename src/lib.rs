@@ -3,10 +3,42 @@ extern crate lazy_static;
 
 extern crate chrono;
 extern crate regex;
+extern crate crc32fast;
+extern crate md5;
+extern crate sha1_smol;
+extern crate sha2;
+extern crate flate2;
+extern crate toml;
 
 mod ftp;
+mod data;
 pub mod types;
 pub mod status;
+pub mod engine;
+pub mod progress;
+pub mod throttle;
+pub mod checksum;
+pub mod mtime;
+pub mod listing;
+pub mod walk;
+pub mod glob;
+pub mod mirror;
+pub mod sync;
+pub mod netrc;
+pub mod config;
+pub mod url;
+pub mod server;
+#[cfg(any(test, feature = "test-support"))]
+pub mod mock;
 
-pub use ftp::FtpStream;
-pub use types::FtpError;
+pub use ftp::{FtpStream, ANONYMOUS_USER, ANONYMOUS_PASSWORD};
+pub use data::{UploadStream, DownloadStream};
+pub use types::{FtpError, Tls};
+pub use engine::{TransferEngine, Job};
+pub use progress::{Progress, ProgressObserver};
+pub use throttle::RateLimiter;
+pub use listing::{Entry, EntryType};
+pub use walk::{Walk, WalkEntry};
+pub use mirror::{Mirror, Plan};
+pub use sync::{TwoWaySync, SyncPlan};
+pub use url::FtpUrl;
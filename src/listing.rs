@@ -0,0 +1,233 @@
+use chrono::{DateTime, UTC, TimeZone, Datelike};
+use regex::Regex;
+
+lazy_static! {
+    static ref UNIX_RE: Regex = Regex::new(
+        r"^([-dlcbpsD])[-rwxsStTl]{9}\S*\s+\d+\s+\S+\s+(?:\S+\s+)?(\d+)\s+([A-Za-z]{3})\s+(\d{1,2})\s+(?:(\d{1,2}):(\d{2})|(\d{4}))\s(.+)$"
+    ).unwrap();
+    static ref DOS_RE: Regex = Regex::new(
+        r"^(\d{2})-(\d{2})-(\d{2}|\d{4})\s+(\d{1,2}):(\d{2})([AaPp][Mm])\s+(<DIR>|\d+)\s+(.+)$"
+    ).unwrap();
+    static ref MODIFY_RE: Regex = Regex::new(r"^(\d{4})(\d{2})(\d{2})(\d{2})(\d{2})(\d{2})").unwrap();
+}
+
+const MONTHS: [&'static str; 12] = ["jan", "feb", "mar", "apr", "may", "jun",
+                                    "jul", "aug", "sep", "oct", "nov", "dec"];
+
+/// The type of a remote directory entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryType {
+    File,
+    Dir,
+    Symlink,
+    Other,
+}
+
+/// An entry of a remote directory, parsed from a MLSD or LIST line
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub name: String,
+    pub entry_type: EntryType,
+    pub size: Option<u64>,
+    pub modified: Option<DateTime<UTC>>,
+    /// Permission bits, from the `unix.mode` fact or the LIST mode string
+    pub mode: Option<u32>,
+    /// Where a symbolic link points to, when the server tells it
+    pub target: Option<String>,
+    /// The MLSD `unique` fact, identifying the file on the server
+    pub unique: Option<String>,
+}
+
+impl Entry {
+    pub(crate) fn new(name: String, entry_type: EntryType) -> Entry {
+        Entry {
+            name: name,
+            entry_type: entry_type,
+            size: None,
+            modified: None,
+            mode: None,
+            target: None,
+            unique: None,
+        }
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.entry_type == EntryType::Dir
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.entry_type == EntryType::File
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        self.entry_type == EntryType::Symlink
+    }
+}
+
+/// Parses a MLSD line (RFC 3659), e.g. `type=file;size=42;modify=20200102030405; notes.txt`
+///
+/// The entries for the listed directory and its parent (`cdir`, `pdir`) yield None, as do
+/// names which aren't plain file names like `..` or `a/b`.
+pub fn parse_mlsd(line: &str) -> Option<Entry> {
+    let split = match line.find(' ') {
+        Some(split) => split,
+        None => return None,
+    };
+    let (facts, name) = (&line[..split], &line[split + 1..]);
+    if !plain_name(name) || !facts.contains('=') {
+        return None;
+    }
+
+    let mut entry = Entry::new(name.to_string(), EntryType::Other);
+    for fact in facts.split(';').filter(|fact| fact.len() > 0) {
+        let eq = match fact.find('=') {
+            Some(eq) => eq,
+            None => continue,
+        };
+        let (key, value) = (fact[..eq].to_lowercase(), &fact[eq + 1..]);
+        match key.as_ref() {
+            "type" => {
+                let lower = value.to_lowercase();
+                entry.entry_type = match lower.as_ref() {
+                    "file" => EntryType::File,
+                    "dir" => EntryType::Dir,
+                    "cdir" | "pdir" => return None,
+                    "os.unix=symlink" => EntryType::Symlink,
+                    _ if lower.starts_with("os.unix=slink") => {
+                        // the target follows a colon on servers telling it
+                        entry.target = value.find(':').map(|colon| value[colon + 1..].to_string())
+                            .and_then(|target| if target.is_empty() { None } else { Some(target) });
+                        EntryType::Symlink
+                    }
+                    _ => EntryType::Other,
+                };
+            }
+            "size" | "sizd" => entry.size = value.parse().ok(),
+            "modify" => entry.modified = parse_modify(value),
+            "unix.mode" => entry.mode = u32::from_str_radix(value, 8).ok(),
+            "unique" => entry.unique = Some(value.to_string()),
+            _ => (),
+        }
+    }
+    Some(entry)
+}
+
+/// Parses a LIST line in the Unix `ls -l` or the DOS format
+///
+/// Lines in other formats, like the `total` header, and names which aren't plain file names
+/// yield None. Times without a year are taken to be within the last year.
+pub fn parse_list(line: &str) -> Option<Entry> {
+    parse_unix(line).or_else(|| parse_dos(line))
+}
+
+fn parse_unix(line: &str) -> Option<Entry> {
+    let caps = match UNIX_RE.captures(line) {
+        Some(caps) => caps,
+        None => return None,
+    };
+
+    let entry_type = match &caps[1] {
+        "-" => EntryType::File,
+        "d" => EntryType::Dir,
+        "l" => EntryType::Symlink,
+        _ => EntryType::Other,
+    };
+
+    let mut name = caps[8].to_string();
+    let mut target = None;
+    if entry_type == EntryType::Symlink {
+        if let Some(arrow) = name.find(" -> ") {
+            target = Some(name[arrow + 4..].to_string());
+            name.truncate(arrow);
+        }
+    }
+    if !plain_name(&name) {
+        return None;
+    }
+
+    let month = MONTHS.iter().position(|month| *month == caps[3].to_lowercase()).map(|m| m as u32 + 1);
+    let day = caps[4].parse::<u32>().ok();
+    let modified = match (month, day, caps.at(7)) {
+        (Some(month), Some(day), Some(year)) => {
+            year.parse().ok().and_then(|year| to_utc(year, month, day, 0, 0, 0))
+        }
+        (Some(month), Some(day), None) => {
+            let (hour, minute) = (caps[5].parse().unwrap_or(0), caps[6].parse().unwrap_or(0));
+            let now = UTC::now();
+            to_utc(now.year(), month, day, hour, minute, 0).and_then(|time| {
+                if time > now + ::chrono::Duration::days(1) {
+                    to_utc(now.year() - 1, month, day, hour, minute, 0)
+                } else {
+                    Some(time)
+                }
+            })
+        }
+        _ => None,
+    };
+
+    let mut entry = Entry::new(name, entry_type);
+    entry.size = caps[2].parse().ok();
+    entry.modified = modified;
+    entry.mode = Some(parse_mode(&line[1..10]));
+    entry.target = target;
+    Some(entry)
+}
+
+fn parse_dos(line: &str) -> Option<Entry> {
+    let caps = match DOS_RE.captures(line) {
+        Some(caps) if plain_name(&caps[8]) => caps,
+        _ => return None,
+    };
+
+    let (month, day) = (caps[1].parse().unwrap_or(0), caps[2].parse().unwrap_or(0));
+    let year = match caps[3].parse::<i32>().unwrap_or(0) {
+        year if year >= 100 => year,
+        year if year >= 70 => 1900 + year,
+        year => 2000 + year,
+    };
+    let hour = caps[4].parse::<u32>().unwrap_or(0) % 12;
+    let hour = if caps[6].to_lowercase() == "pm" { hour + 12 } else { hour };
+    let minute = caps[5].parse().unwrap_or(0);
+
+    let mut entry = if &caps[7] == "<DIR>" {
+        Entry::new(caps[8].to_string(), EntryType::Dir)
+    } else {
+        let mut entry = Entry::new(caps[8].to_string(), EntryType::File);
+        entry.size = caps[7].parse().ok();
+        entry
+    };
+    entry.modified = to_utc(year, month, day, hour, minute, 0);
+    Some(entry)
+}
+
+/// Whether a listed name is the name of an entry of the listed directory
+///
+/// `.`, `..` and names holding a separator would lead elsewhere, like the `../../.bashrc` a
+/// hostile server could list to have a recursive download write outside its directory.
+fn plain_name(name: &str) -> bool {
+    !name.is_empty() && name != "." && name != ".." && !name.contains('/') && !name.contains('\\')
+}
+
+/// Parses a `rwxr-xr-x` string into permission bits
+fn parse_mode(perms: &str) -> u32 {
+    perms.chars().enumerate().fold(0, |mode, (i, c)| {
+        match c {
+            '-' | 'S' | 'T' | 'l' => mode,
+            _ => mode | 1 << (8 - i),
+        }
+    })
+}
+
+fn parse_modify(value: &str) -> Option<DateTime<UTC>> {
+    MODIFY_RE.captures(value).and_then(|caps| {
+        to_utc(caps[1].parse().unwrap_or(0), caps[2].parse().unwrap_or(0), caps[3].parse().unwrap_or(0),
+               caps[4].parse().unwrap_or(0), caps[5].parse().unwrap_or(0), caps[6].parse().unwrap_or(0))
+    })
+}
+
+fn to_utc(year: i32, month: u32, day: u32, hour: u32, minute: u32, second: u32) -> Option<DateTime<UTC>> {
+    UTC.ymd_opt(year, month, day).single().and_then(|date| date.and_hms_opt(hour, minute, second))
+}
+
+#[cfg(test)]
+mod tests;
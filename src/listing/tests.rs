@@ -0,0 +1,68 @@
+use chrono::{UTC, TimeZone};
+use super::{parse_list, parse_mlsd, EntryType};
+
+#[test]
+fn unix() {
+    let entry = parse_list("-rw-r--r--    1 ftp      ftp          1024 Mar 14  2019 notes.txt").unwrap();
+    assert_eq!((&entry.name[..], entry.entry_type, entry.size), ("notes.txt", EntryType::File, Some(1024)));
+    assert_eq!(entry.modified, Some(UTC.ymd(2019, 3, 14).and_hms(0, 0, 0)));
+    assert_eq!(entry.mode, Some(0o644));
+
+    // some servers leave out the group, names may hold spaces
+    let entry = parse_list("drwxr-x---   2 owner 4096 Jan  5  2020 my documents").unwrap();
+    assert!(entry.is_dir());
+    assert_eq!(entry.name, "my documents");
+    assert_eq!(entry.mode, Some(0o750));
+
+    let entry = parse_list("lrwxrwxrwx 1 ftp ftp 11 Mar 14  2019 latest -> releases/1.0").unwrap();
+    assert!(entry.is_symlink());
+    assert_eq!(entry.name, "latest");
+    assert_eq!(entry.target, Some(String::from("releases/1.0")));
+}
+
+#[test]
+fn dos() {
+    let entry = parse_list("01-15-21  03:04PM       <DIR>          Reports 2021").unwrap();
+    assert!(entry.is_dir());
+    assert_eq!(entry.name, "Reports 2021");
+    assert_eq!(entry.modified, Some(UTC.ymd(2021, 1, 15).and_hms(15, 4, 0)));
+
+    let entry = parse_list("12-31-99  12:30AM                 1234 old.txt").unwrap();
+    assert_eq!(entry.size, Some(1234));
+    assert_eq!(entry.modified, Some(UTC.ymd(1999, 12, 31).and_hms(0, 30, 0)));
+}
+
+#[test]
+fn malformed() {
+    assert_eq!(parse_list("total 12"), None);
+    assert_eq!(parse_list("drwxr-xr-x"), None);
+    assert_eq!(parse_list("-rw-r--r-- 1 ftp ftp many Mar 14  2019 notes.txt"), None);
+    assert_eq!(parse_list("01-15-21 <DIR> Reports"), None);
+    // an impossible date leaves the time unknown
+    assert_eq!(parse_list("-rw-r--r-- 1 ftp ftp 1 Feb 30  2019 notes.txt").unwrap().modified, None);
+}
+
+#[test]
+fn mlsd() {
+    let entry = parse_mlsd("type=file;size=42;modify=20200102030405;unix.mode=0644;unique=801U1; notes.txt").unwrap();
+    assert!(entry.is_file());
+    assert_eq!((entry.size, entry.mode), (Some(42), Some(0o644)));
+    assert_eq!(entry.modified, Some(UTC.ymd(2020, 1, 2).and_hms(3, 4, 5)));
+    assert_eq!(entry.unique, Some(String::from("801U1")));
+
+    let entry = parse_mlsd("type=OS.unix=slink:/srv/releases/1.0; latest").unwrap();
+    assert!(entry.is_symlink());
+    assert_eq!(entry.target, Some(String::from("/srv/releases/1.0")));
+
+    assert_eq!(parse_mlsd("type=cdir; ."), None);
+    assert_eq!(parse_mlsd("type=pdir; .."), None);
+    assert_eq!(parse_mlsd("notes.txt"), None);
+
+    // names leading out of the listed directory
+    assert_eq!(parse_mlsd("type=file;size=1; ../../.bashrc"), None);
+    assert_eq!(parse_mlsd("type=dir; /etc"), None);
+    assert_eq!(parse_list("-rw-r--r-- 1 ftp ftp 1 Mar 14  2019 ../.bashrc"), None);
+    assert_eq!(parse_list("drwxr-xr-x 2 ftp ftp 1 Mar 14  2019 .."), None);
+    assert_eq!(parse_list("lrwxrwxrwx 1 ftp ftp 1 Mar 14  2019 a/b -> c"), None);
+    assert_eq!(parse_list("01-15-21  03:04PM  1 ..\\boot.ini"), None);
+}
@@ -1,19 +1,38 @@
 extern crate FTPCLI;
 extern crate chrono;
 extern crate rpassword;
+extern crate getopts;
 #[macro_use] extern crate lazy_static;
 
 use std::io as stdio;
+use std::fs;
 use std::fs::File;
-use std::io::{Write, BufReader};
+use std::io::{Write, BufReader, IsTerminal, copy};
 use std::process::exit;
 use std::env;
 use std::iter::Iterator;
-use std::collections::HashMap;
-use chrono::{Timelike, Datelike};
+use std::collections::{HashMap, VecDeque};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use chrono::{Timelike, Datelike, TimeZone, UTC};
 use rpassword::prompt_password_stdout;
+use getopts::{Options, Matches};
 
-use FTPCLI::{FtpStream, FtpError};
+use FTPCLI::{FtpStream, FtpError, TransferEngine, Job, Progress, ProgressObserver, RateLimiter, Tls, FtpUrl};
+use FTPCLI::types::Reply;
+use FTPCLI::{ANONYMOUS_USER, ANONYMOUS_PASSWORD};
+use FTPCLI::engine::{Event, JobResult};
+use FTPCLI::progress::Direction;
+use FTPCLI::checksum::{Algorithm, file_checksum};
+use FTPCLI::mtime::{to_utc, set_local_mtime};
+use FTPCLI::glob::{Filter, is_pattern, glob_local};
+use FTPCLI::mirror::Mirror;
+use FTPCLI::sync::{TwoWaySync, Policy, Conflict, Resolution, Stamp};
+use FTPCLI::walk::{join, local_path, walk_local};
+use FTPCLI::netrc::{Netrc, Machine};
+use FTPCLI::throttle::parse_rate;
+use FTPCLI::config::{Config, Profile};
 
 lazy_static! {
     static ref CMD_SET: Vec<&'static str> = {
@@ -34,6 +53,28 @@ lazy_static! {
         cmds.push("size");
         cmds.push("nlist");
         cmds.push("modtime");
+        cmds.push("mget");
+        cmds.push("mput");
+        cmds.push("mdelete");
+        cmds.push("prompt");
+        cmds.push("parallel");
+        cmds.push("rate");
+        cmds.push("checksum");
+        cmds.push("verify");
+        cmds.push("chmod");
+        cmds.push("umask");
+        cmds.push("site");
+        cmds.push("append");
+        cmds.push("sunique");
+        cmds.push("compress");
+        cmds.push("touch");
+        cmds.push("preserve");
+        cmds.push("tree");
+        cmds.push("mirror");
+        cmds.push("sync");
+        cmds.push("fxp");
+        cmds.push("$");
+        cmds.push("profiles");
         
         cmds
     };
@@ -44,34 +85,144 @@ lazy_static! {
         map.insert("exit", "terminate ftp session and exit");
         map.insert("ls", "list contents of remote directory");
         map.insert("pwd", "print working directory on remote machine");
-        map.insert("get", "retrive file");
-        map.insert("put", "send one file");
+        map.insert("get", "retrive file, `get -r [-i glob] [-x glob] <dir> [local]` retrives a directory");
+        map.insert("put", "send one file, `put -r [-i glob] [-x glob] <dir> [remote]` sends a directory");
         map.insert("cd", "change remote working directory");
         map.insert("help", "print local help information");
         map.insert("cdup", "change remote working directory to parent directory");
-        map.insert("mkdir", "make directory on the remote machine");
-        map.insert("rmdir", "remove directory on the remote machine");
+        map.insert("mkdir", "make directory on the remote machine, `-p` also makes missing parents");
+        map.insert("rmdir", "remove directory on the remote machine, `-r` also removes its contents");
         map.insert("delete", "delete remote file");
         map.insert("size", "show the size of remote file");
         map.insert("nlist", "nlist contents of remote directory");
         map.insert("modtime", "show last modification time of remote file");
+        map.insert("mget", "get multiple files, wildcards like `*.csv` are expanded on the remote side");
+        map.insert("mput", "send multiple files, wildcards like `*.csv` are expanded on the local side");
+        map.insert("mdelete", "delete multiple remote files, wildcards are expanded on the remote side");
+        map.insert("prompt", "toggle asking about each file in mget, mput and mdelete");
+        map.insert("parallel", "set number of concurrent connections used by mget and mput");
+        map.insert("rate", "limit transfer rate in bytes per second, `rate [get|put] <rate|off>`");
+        map.insert("checksum", "show checksum of remote file, `checksum <crc32|md5|sha1|sha256> <remote> [local]`");
+        map.insert("verify", "verify checksum after each transfer, `verify [<crc32|md5|sha1|sha256>|off]`");
+        map.insert("chmod", "change file permissions of remote file, `chmod <mode> <file>`");
+        map.insert("umask", "get (set) umask on remote side");
+        map.insert("site", "send site specific command to remote server");
+        map.insert("append", "append to a file");
+        map.insert("sunique", "toggle store unique on remote machine");
+        map.insert("compress", "toggle compressed transfers (MODE Z)");
+        map.insert("touch", "set modification time of remote file, `touch <file> [YYYYMMDDHHMMSS]`");
+        map.insert("preserve", "preserve modification times of transferred files, `preserve [on|off|auto]`");
+        map.insert("mirror", "mirror remote directory, `mirror [-R] [-n] [-d] [-c algorithm] [-i glob] [-x glob] <src> [dest]`");
+        map.insert("sync", "sync remote and local directory both ways, `sync [-n] [-p newer|both|ask] [-i glob] [-x glob] <remote> [local]`");
+        map.insert("fxp", "copy remote file straight to another server, `fxp <file> <host[:port]> [dest]`");
+        map.insert("$", "run a macro from .netrc, `$ <name> [args]` replaces $1, $2... by the args");
+        map.insert("profiles", "list the host profiles of the configuration file");
+        map.insert("tree", "list remote directory recursively, `tree [-L] [path] [depth]`");
         
         map
     };
 }
 
+/// How the session logged in, the connections of batches log in the same way
+#[derive(Clone)]
+struct Credentials {
+    user: String,
+    password: String,
+    /// Sent with ACCT if the server asks for it
+    account: Option<String>,
+}
+
+impl Credentials {
+    fn new(user: String, password: String, account: Option<&str>) -> Credentials {
+        Credentials { user: user, password: password, account: account.map(String::from) }
+    }
+}
+
+/// State kept across commands of one session
+struct Session {
+    addr: String,
+    credentials: Option<Credentials>,
+    workers: usize,
+    download_limit: RateLimiter,
+    upload_limit: RateLimiter,
+    verify: Option<Algorithm>,
+    sunique: bool,
+    compress: bool,
+    /// None keeps the times for batches only
+    preserve: Option<bool>,
+    /// Whether `mget`, `mput` and `mdelete` ask about each file
+    prompt: bool,
+    /// The `macdef` macros of the host's .netrc entry
+    macros: HashMap<String, Vec<String>>,
+    /// Command lines of running macros, read before stdin
+    pending: VecDeque<String>,
+    passive: bool,
+    timeout: Option<Duration>,
+    config: Config,
+    /// Leaves out the chatter of one-shot commands
+    quiet: bool,
+}
+
+impl Session {
+    fn new(addr: &str) -> Session {
+        Session {
+            addr: addr.to_string(),
+            credentials: None,
+            workers: 4,
+            download_limit: RateLimiter::new(0),
+            upload_limit: RateLimiter::new(0),
+            verify: None,
+            sunique: false,
+            compress: false,
+            preserve: None,
+            prompt: true,
+            macros: HashMap::new(),
+            pending: VecDeque::new(),
+            passive: true,
+            timeout: None,
+            config: Config::default(),
+            quiet: false,
+        }
+    }
+
+    /// Whether a single `get` or `put` keeps the modification time
+    fn preserve_single(&self) -> bool {
+        self.preserve.unwrap_or(false)
+    }
+
+    /// Whether batches like `mget` keep the modification times, mirroring the source files
+    fn preserve_batch(&self) -> bool {
+        self.preserve.unwrap_or(true)
+    }
+}
 
-fn cmd_loop(ftp_stream: &mut FtpStream) -> ! {
+fn cmd_loop(ftp_stream: &mut FtpStream, session: &mut Session) -> ! {
     'looper: loop {
         print!("ftp> ");
         stdio::stdout().flush().unwrap();
         
-        let mut cmd_line = String::new();
-        stdio::stdin().read_line(&mut cmd_line).unwrap();
+        let cmd_line = match session.pending.pop_front() {
+            Some(line) => {
+                println!("{}", line);
+                line
+            }
+            None => {
+                let mut cmd_line = String::new();
+                if stdio::stdin().read_line(&mut cmd_line).unwrap() == 0 {
+                    // end of input, as after `exit`
+                    println!("Bye");
+                    break 'looper;
+                }
+                cmd_line
+            }
+        };
 
         let cmds: Vec<String> = cmd_line.split(' ').into_iter()
             .map(|s| String::from(s.trim()))
             .filter(|s| s.len() > 0).collect();
+        if cmds.len() == 0 {
+            continue;
+        }
 
         if cfg!(feature = "debug_print") {
             println!("CMD {}", cmds[0]);
@@ -79,14 +230,14 @@ fn cmd_loop(ftp_stream: &mut FtpStream) -> ! {
         
         match cmds[0].as_ref() {
             "login"=> {
-                login(ftp_stream);
+                login(ftp_stream, session);
             },
             "exit" => {
                 println!("Bye");
                 break 'looper;
             },
             "ls" => {
-                let path = match cmds.capacity() {
+                let path = match cmds.len() {
                     1 => Some("."),
                     _ => Some(cmds[1].as_ref()),
                 };
@@ -96,30 +247,112 @@ fn cmd_loop(ftp_stream: &mut FtpStream) -> ! {
                 pwd(ftp_stream);
             },
             "get" => {
-                if cmds.capacity() <= 1 {
+                let (recursive, filter, args) = match tree_args(&cmds[1..]) {
+                    Some(parsed) => parsed,
+                    None => continue,
+                };
+                if args.len() == 0 {
                     println!("Invalid arguements");
                     continue;
                 }
-                let (src, desc) = match cmds.capacity() {
-                    2 => (cmds[1].clone(), cmds[1].clone()),
-                    _ => (cmds[1].clone(), cmds[2].clone()),                  
-                };
-                get(ftp_stream, &src, &desc);
+                let src = args[0].clone();
+                if recursive {
+                    let desc = args.get(1).cloned().unwrap_or(basename(&src).to_string());
+                    get_tree(ftp_stream, session, &src, &desc, &filter);
+                } else {
+                    let desc = args.get(1).cloned().unwrap_or(src.clone());
+                    get(ftp_stream, &src, &desc, session);
+                }
             },
             "put" => {
-                match cmds.capacity() {
-                    1 => {
-                        println!("Invalid arguements");
-                        continue;
-                    }
+                let (recursive, filter, args) = match tree_args(&cmds[1..]) {
+                    Some(parsed) => parsed,
+                    None => continue,
+                };
+                if args.len() == 0 {
+                    println!("Invalid arguements");
+                    continue;
+                }
+                let src = args[0].clone();
+                if recursive {
+                    let desc = args.get(1).cloned().unwrap_or(basename(&src).to_string());
+                    put_tree(ftp_stream, session, &src, &desc, &filter);
+                } else {
+                    let store = if session.sunique { Store::Unique } else { Store::Replace };
+                    let desc = args.get(1).cloned().unwrap_or(src.clone());
+                    put(ftp_stream, &src, &desc, store, session);
+                }
+            },
+            "append" => {
+                match cmds.len() {
+                    1 => println!("Invalid arguements"),
                     2 => {
-                        put(ftp_stream, &cmds[1], &cmds[1]);
+                        put(ftp_stream, &cmds[1], &cmds[1], Store::Append, session);
                     }
                     _ => {
-                        put(ftp_stream, &cmds[1], &cmds[2]);
+                        put(ftp_stream, &cmds[1], &cmds[2], Store::Append, session);
+                    }
+                }
+            },
+            "compress" => {
+                match ftp_stream.set_compression(!session.compress) {
+                    Ok(compressed) if compressed == session.compress => {
+                        println!("Remote server does not support MODE Z");
                     }
+                    Ok(compressed) => {
+                        session.compress = compressed;
+                        println!("Compression {}.", if compressed { "on" } else { "off" });
+                    }
+                    Err(_) => println!("compress command failed"),
+                }
+            },
+            "touch" => {
+                match cmds.len() {
+                    1 => println!("Invalid arguements"),
+                    2 => touch(ftp_stream, &cmds[1], None),
+                    _ => touch(ftp_stream, &cmds[1], Some(&cmds[2])),
                 }
             },
+            "preserve" => {
+                match cmds.get(1).map(|arg| arg.as_ref()) {
+                    None => (()),
+                    Some("on") => session.preserve = Some(true),
+                    Some("off") => session.preserve = Some(false),
+                    Some("auto") => session.preserve = None,
+                    Some(_) => println!("Invalid arguements"),
+                }
+                match session.preserve {
+                    Some(true) => println!("Preserve modification times on."),
+                    Some(false) => println!("Preserve modification times off."),
+                    None => println!("Preserve modification times for mget and mput only."),
+                }
+            },
+            "tree" => {
+                let follow = cmds.iter().any(|arg| arg == "-L");
+                let args: Vec<&String> = cmds.iter().skip(1).filter(|arg| *arg != "-L").collect();
+                let depth = match args.get(1).map(|depth| depth.parse::<usize>()) {
+                    None => None,
+                    Some(Ok(depth)) if depth > 0 => Some(depth),
+                    Some(_) => {
+                        println!("Invalid arguements");
+                        continue;
+                    }
+                };
+                tree(ftp_stream, args.get(0).map_or(".", |path| path.as_ref()), depth, follow);
+            },
+            "mirror" => {
+                mirror(ftp_stream, session, &cmds[1..]);
+            },
+            "sync" => {
+                sync(ftp_stream, session, &cmds[1..]);
+            },
+            "fxp" => {
+                fxp(ftp_stream, session, &cmds[1..]);
+            },
+            "sunique" => {
+                session.sunique = !session.sunique;
+                println!("Store unique {}.", if session.sunique { "on" } else { "off" });
+            },
             "cd" => {
                 match cmds.capacity() {
                     1 => {
@@ -159,10 +392,17 @@ fn cmd_loop(ftp_stream: &mut FtpStream) -> ! {
                 }
             },
             "mkdir" => {
-                match cmds.capacity() {
-                    1 => println!("Invalid arguements"),
+                let parents = cmds.len() > 1 && cmds[1] == "-p";
+                let args = if parents { &cmds[2..] } else { &cmds[1..] };
+                match args.len() {
+                    0 => println!("Invalid arguements"),
                     _ => {
-                        match ftp_stream.mkdir(&cmds[1]) {
+                        let result = if parents {
+                            ftp_stream.mkdir_all(&args[0])
+                        } else {
+                            ftp_stream.mkdir(&args[0])
+                        };
+                        match result {
                             Ok(()) => (()),
                             Err(_) => println!("mkdir command failed"),
                         }
@@ -170,10 +410,13 @@ fn cmd_loop(ftp_stream: &mut FtpStream) -> ! {
                 }
             },
             "rmdir" => {
-                match cmds.capacity() {
-                    1 => println!("Invalid arguements"),
+                let recursive = cmds.len() > 1 && cmds[1] == "-r";
+                let args = if recursive { &cmds[2..] } else { &cmds[1..] };
+                match args.len() {
+                    0 => println!("Invalid arguements"),
+                    _ if recursive => rmdir_all(ftp_stream, &args[0]),
                     _ => {
-                        match ftp_stream.rmdir(&cmds[1]) {
+                        match ftp_stream.rmdir(&args[0]) {
                             Ok(()) => (()),
                             Err(_) => println!("rmdir command failed"),
                         }
@@ -181,7 +424,7 @@ fn cmd_loop(ftp_stream: &mut FtpStream) -> ! {
                 }
             },
             "delete" => {
-                match cmds.capacity() {
+                match cmds.len() {
                     1 => println!("Invalid arguements"),
                     _ => {
                         match ftp_stream.rm(&cmds[1]) {
@@ -192,23 +435,176 @@ fn cmd_loop(ftp_stream: &mut FtpStream) -> ! {
                 }
             },
             "size" => {
-                match cmds.capacity() {
+                match cmds.len() {
                     1 => println!("Invalid arguements"),
                     _ => size(ftp_stream, &cmds[1]),
                 }
             },
             "nlist" => {
-                match cmds.capacity() {
+                match cmds.len() {
                     1 => nlist(ftp_stream, None),
                     _ => nlist(ftp_stream, Some(&cmds[1])),
                 }
             },
             "modtime" => {
-                match cmds.capacity() {
+                match cmds.len() {
                     1 => println!("Invalid arguements"),
                     _ => modtime(ftp_stream, &cmds[1]),
                 }
             },
+            "mget" => {
+                match cmds.len() {
+                    1 => println!("Invalid arguements"),
+                    _ => {
+                        let remotes = remote_glob(ftp_stream, &cmds[1..]);
+                        let jobs: Vec<Job> = choose(session, "mget", remotes).iter()
+                            .map(|remote| Job::get(remote, basename(remote)))
+                            .collect();
+                        if jobs.len() > 0 {
+                            transfer(ftp_stream, session, jobs);
+                        }
+                    }
+                }
+            },
+            "mput" => {
+                match cmds.len() {
+                    1 => println!("Invalid arguements"),
+                    _ => {
+                        let locals = local_glob(&cmds[1..]);
+                        let jobs: Vec<Job> = choose(session, "mput", locals).iter()
+                            .map(|local| Job::put(local, basename(local)))
+                            .collect();
+                        if jobs.len() > 0 {
+                            transfer(ftp_stream, session, jobs);
+                        }
+                    }
+                }
+            },
+            "mdelete" => {
+                match cmds.len() {
+                    1 => println!("Invalid arguements"),
+                    _ => {
+                        let remotes = remote_glob(ftp_stream, &cmds[1..]);
+                        for remote in choose(session, "mdelete", remotes) {
+                            if ftp_stream.rm(&remote).is_err() {
+                                println!("delete {} failed", remote);
+                            }
+                        }
+                    }
+                }
+            },
+            "prompt" => {
+                session.prompt = !session.prompt;
+                println!("Interactive mode {}.", if session.prompt { "on" } else { "off" });
+            },
+            "parallel" => {
+                match cmds.len() {
+                    1 => println!("Using {} connections", session.workers),
+                    _ => {
+                        match cmds[1].parse::<usize>() {
+                            Ok(n) if n > 0 => session.workers = n,
+                            _ => println!("Invalid arguements"),
+                        }
+                    }
+                }
+            },
+            "rate" => {
+                rate(session, &cmds[1..]);
+            },
+            "checksum" => {
+                match cmds.len() {
+                    1 | 2 => println!("Invalid arguements"),
+                    3 => checksum(ftp_stream, &cmds[1], &cmds[2], None),
+                    _ => checksum(ftp_stream, &cmds[1], &cmds[2], Some(&cmds[3])),
+                }
+            },
+            "verify" => {
+                match cmds.len() {
+                    1 => match session.verify {
+                        Some(algorithm) => println!("Verifying transfers with {}", algorithm),
+                        None => println!("Verification is off"),
+                    },
+                    _ => {
+                        let verify = match cmds[1].as_ref() {
+                            "off" => Ok(None),
+                            algorithm => algorithm.parse::<Algorithm>().map(Some),
+                        };
+                        match verify {
+                            Ok(verify) => {
+                                session.verify = verify;
+                                ftp_stream.set_checksum(verify);
+                                ftp_stream.set_verify(verify.is_some());
+                            }
+                            Err(err) => println!("{}", err),
+                        }
+                    }
+                }
+            },
+            "chmod" => {
+                match cmds.len() {
+                    1 | 2 => println!("Invalid arguements"),
+                    _ => {
+                        match u32::from_str_radix(&cmds[1], 8) {
+                            Ok(mode) => {
+                                match ftp_stream.site_chmod(mode, &cmds[2]) {
+                                    Ok(()) => (()),
+                                    Err(_) => println!("chmod command failed"),
+                                }
+                            }
+                            Err(_) => println!("Invalid mode {}", cmds[1]),
+                        }
+                    }
+                }
+            },
+            "umask" => {
+                let umask = match cmds.len() {
+                    1 => Ok(None),
+                    _ => u32::from_str_radix(&cmds[1], 8).map(Some),
+                };
+                match umask {
+                    Ok(umask) => {
+                        match ftp_stream.site_umask(umask) {
+                            Ok(()) => (()),
+                            Err(_) => println!("umask command failed"),
+                        }
+                    }
+                    Err(_) => println!("Invalid umask {}", cmds[1]),
+                }
+            },
+            "site" => {
+                match cmds.len() {
+                    1 => println!("Invalid arguements"),
+                    _ => {
+                        // replies like SITE HELP's are shown whole
+                        ftp_stream.set_verbose(false);
+                        let reply = ftp_stream.site(&cmds[1..].join(" "));
+                        ftp_stream.set_verbose(true);
+                        match reply {
+                            Ok(Reply(_, lines)) => {
+                                for line in lines {
+                                    print!("{}", line);
+                                }
+                            }
+                            Err(_) => println!("site command failed"),
+                        }
+                    }
+                }
+            },
+            "profiles" => {
+                profiles(&session.config);
+            },
+            "$" => {
+                match cmds.len() {
+                    1 => {
+                        let mut names: Vec<&String> = session.macros.keys().collect();
+                        names.sort();
+                        for name in names {
+                            println!("{}", name);
+                        }
+                    }
+                    _ => run_macro(session, &cmds[1], &cmds[2..]),
+                }
+            },
             _ => {
                 println!("Invalid command or not implemented!");
             }
@@ -217,38 +613,226 @@ fn cmd_loop(ftp_stream: &mut FtpStream) -> ! {
     exit(-1);
 }
 
-fn login(ftp_stream: &mut FtpStream) {
-    print!("Name: ");
-    stdio::stdout().flush().unwrap();
-    let mut name: String = String::new();
-    // let mut passwd: String = String::new();
+fn login(ftp_stream: &mut FtpStream, session: &mut Session) {
+    if let Some((name, passwd)) = ask_login(ftp_stream, None) {
+        session.credentials = Some(Credentials::new(name, passwd, None));
+        println!("Login successfully");
+    }
+}
+
+/// Logs in right after connecting, as asked on the command line, by the profile or by the URL
+///
+/// A URL without user name logs in anonymously unless .netrc has an entry for the host.
+fn auto_login(ftp_stream: &mut FtpStream, session: &mut Session, matches: &Matches, profile: &Profile,
+              url: Option<&FtpUrl>) {
+    let user = matches.opt_str("user");
+    let anonymous = matches.opt_present("anonymous") || (user.is_none() && profile.anonymous == Some(true));
+    if anonymous {
+        anonymous_login(ftp_stream, session, matches.opt_str("email"));
+        return;
+    }
+
+    // the password of the profile belongs to the user of the profile
+    if let (Some(name), Some(passwd)) = (profile.user.clone(), profile.password.clone()) {
+        if user.is_none() || user.as_ref() == Some(&name) {
+            let account = profile.account.as_ref().map(|account| &account[..]);
+            password_login(ftp_stream, session, name, passwd, account);
+            return;
+        }
+    }
+    let user = user.or(profile.user.clone());
+    if let Some(machine) = netrc_machine(profile.host.as_ref().unwrap()) {
+        // an entry for another user than the one asked for is of no use
+        if machine.login.is_some() && (user.is_none() || user == machine.login) {
+            netrc_login(ftp_stream, session, machine);
+            return;
+        }
+    }
+    if user.is_none() && url.is_some() {
+        anonymous_login(ftp_stream, session, matches.opt_str("email"));
+        return;
+    }
+    if let Some((name, passwd)) = ask_login(ftp_stream, user.as_ref().map(|user| &user[..])) {
+        session.credentials = Some(Credentials::new(name, passwd, None));
+        if !session.quiet {
+            println!("Login successfully");
+        }
+    }
+}
+
+fn anonymous_login(ftp_stream: &mut FtpStream, session: &mut Session, email: Option<String>) {
+    match ftp_stream.login_anonymous(email.as_ref().map(|email| &email[..])) {
+        Ok(()) => {
+            let password = email.unwrap_or_else(|| ANONYMOUS_PASSWORD.to_string());
+            session.credentials = Some(Credentials::new(ANONYMOUS_USER.to_string(), password, None));
+            if !session.quiet {
+                println!("Login successfully");
+            }
+        }
+        Err(_) => eprintln!("Login failed"),
+    }
+}
+
+fn password_login(ftp_stream: &mut FtpStream, session: &mut Session, name: String, passwd: String,
+                  account: Option<&str>) -> bool {
+    match ftp_stream.login_account(&name, &passwd, account) {
+        Ok(()) => {
+            if !session.quiet {
+                println!("Login successfully as {}", name);
+            }
+            session.credentials = Some(Credentials::new(name, passwd, account));
+            true
+        }
+        Err(_) => {
+            eprintln!("Login failed");
+            false
+        }
+    }
+}
+
+/// Applies the settings of the profile which need a logged in session
+fn setup(ftp_stream: &mut FtpStream, session: &mut Session, profile: &Profile) {
+    if profile.compress == Some(true) {
+        match ftp_stream.set_compression(true) {
+            Ok(true) => session.compress = true,
+            Ok(false) => eprintln!("The server does not support MODE Z"),
+            Err(_) => eprintln!("compress command failed"),
+        }
+    }
+    if let Some(ref dir) = profile.dir {
+        cd(ftp_stream, dir);
+    }
+}
+
+/// Changes to the path of a URL, or to its parent if the path is a file
+fn url_cd(ftp_stream: &mut FtpStream, url: &FtpUrl) {
+    let path = match url.path.trim_end_matches('/') {
+        "" if url.path.is_empty() => return,
+        "" => "/",
+        path => path,
+    };
+    if ftp_stream.cwd(path).is_ok() {
+        return;
+    }
+    match url.dir() {
+        Some(dir) if !url.path.ends_with('/') => cd(ftp_stream, dir),
+        _ => println!("change directory failed"),
+    }
+}
+
+/// The .netrc entry of `host`, if there is a readable .netrc
+fn netrc_machine(host: &str) -> Option<Machine> {
+    let path = match Netrc::default_path() {
+        Some(path) => path,
+        None => return None,
+    };
+    let netrc = match Netrc::load(&path) {
+        Ok(netrc) => netrc,
+        Err(ref err) if err.kind() == stdio::ErrorKind::NotFound => return None,
+        Err(err) => {
+            println!("Ignoring {}: {}", path.display(), err);
+            return None;
+        }
+    };
+    let mut machine = match netrc.find(host) {
+        Some(machine) => machine.clone(),
+        None => return None,
+    };
+    if machine.password.is_some() && readable_by_others(&path) {
+        println!("Ignoring the password in {}, the file is readable by others", path.display());
+        machine.password = None;
+    }
+    Some(machine)
+}
+
+#[cfg(unix)]
+fn readable_by_others(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(path).map(|meta| meta.permissions().mode() & 0o077 != 0).unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn readable_by_others(_path: &Path) -> bool {
+    false
+}
+
+/// Logs in with the login of a .netrc entry, asking for the password if it has none, and
+/// queues its `init` macro
+fn netrc_login(ftp_stream: &mut FtpStream, session: &mut Session, machine: Machine) {
+    let name = machine.login.unwrap();
+    let passwd = match machine.password {
+        Some(passwd) => passwd,
+        None => prompt_password_stdout("Password: ").unwrap(),
+    };
+    if !password_login(ftp_stream, session, name, passwd, machine.account.as_ref().map(|account| &account[..])) {
+        return;
+    }
+    session.macros = machine.macros;
+    if session.macros.contains_key("init") {
+        run_macro(session, "init", &[]);
+    }
+}
+
+/// Queues the lines of a macro, replacing `$1`, `$2`... by `args`
+fn run_macro(session: &mut Session, name: &str, args: &[String]) {
+    let lines = match session.macros.get(name) {
+        Some(lines) => lines,
+        None => {
+            println!("No macro named {}", name);
+            return;
+        }
+    };
+    // macros may call macros, their lines run before the rest of the caller's
+    for line in lines.iter().rev() {
+        let mut line = line.clone();
+        for (i, arg) in args.iter().enumerate().rev() {
+            line = line.replace(&format!("${}", i + 1), arg);
+        }
+        session.pending.push_front(line);
+    }
+}
 
-    stdio::stdin().read_line(&mut name).unwrap();
+/// Asks for a password, and for a user name unless `name` is given, and logs in with them
+fn ask_login(ftp_stream: &mut FtpStream, name: Option<&str>) -> Option<(String, String)> {
+    let name = match name {
+        Some(name) => name.to_string(),
+        None => {
+            print!("Name: ");
+            stdio::stdout().flush().unwrap();
+            let mut name: String = String::new();
+            stdio::stdin().read_line(&mut name).unwrap();
+            name.trim().to_string()
+        }
+    };
     match ftp_stream.user(&name) {
         Ok(()) => (()),
-        Err(_) => { println!("Login failed"); return; }
+        Err(_) => { eprintln!("Login failed"); return None; }
     };
 
     let passwd = prompt_password_stdout("Password: ").unwrap();
 
     match ftp_stream.pass(&passwd) {
-        Ok(()) => println!("Login successfully"),
-        Err(_) => println!("Login failed"),
+        Ok(()) => Some((name, passwd)),
+        Err(_) => {
+            eprintln!("Login failed");
+            None
+        }
     }
 }
 
-fn ls(ftp_stream: &mut FtpStream, pathname: Option<&str>) {
+fn ls(ftp_stream: &mut FtpStream, pathname: Option<&str>) -> bool {
     let list: Vec<String> = match ftp_stream.list(pathname) {
         Ok(list) => list,
         Err(_) => {
-            println!("no such file or directory");
-            return;
+            eprintln!("no such file or directory");
+            return false;
         }
     };
 
     for item in list {
         println!("{}", item);
     }
+    true
 }
 
 fn pwd(ftp_stream: &mut FtpStream) {
@@ -258,9 +842,12 @@ fn pwd(ftp_stream: &mut FtpStream) {
     }
 }
 
-fn get(ftp_stream: &mut FtpStream, src: &str, desc: &str) {
-    match ftp_stream.retr(src, |stream| {
-        let mut file = File::create(desc).unwrap();
+fn get(ftp_stream: &mut FtpStream, src: &str, desc: &str, session: &Session) -> bool {
+    if !session.quiet {
+        ftp_stream.set_progress(ProgressBar::new());
+    }
+    let result = ftp_stream.retr(src, |stream| {
+        let mut file = try!(File::create(desc));
         let mut buf = [0; 2048];
         
         loop {
@@ -272,31 +859,216 @@ fn get(ftp_stream: &mut FtpStream, src: &str, desc: &str) {
         }
 
         Ok(())
-    }) {
-        Ok(()) => (()),
-        Err(_) => println!("get file {} failed", src),
+    });
+    ftp_stream.clear_progress();
+
+    match result {
+        Ok(()) => {
+            if !session.quiet {
+                verified(ftp_stream);
+            }
+            if session.preserve_single() {
+                let preserved = match ftp_stream.mdtm(src) {
+                    Ok(Some(mtime)) => set_local_mtime(desc, &mtime).is_ok(),
+                    _ => false,
+                };
+                if !preserved {
+                    eprintln!("could not preserve modification time of {}", desc);
+                }
+            }
+            true
+        }
+        Err(FtpError::ChecksumMismatch(desc)) => {
+            eprintln!("checksum mismatch: {}", desc);
+            false
+        }
+        Err(_) => {
+            eprintln!("get file {} failed", src);
+            false
+        }
     }
 }
 
 fn cd(ftp_stream: &mut FtpStream, pathname: &str) {
     match ftp_stream.cwd(pathname) {
         Ok(()) => (()),
-        Err(_) => println!("change directory failed"),
+        Err(_) => eprintln!("change directory failed"),
     }
 }
 
-fn put(ftp_stream: &mut FtpStream, src: &str, desc: &str) {
+/// How `put` stores the file on the server
+#[derive(Clone, Copy)]
+enum Store {
+    Replace,
+    Append,
+    Unique,
+}
+
+fn put(ftp_stream: &mut FtpStream, src: &str, desc: &str, store: Store, session: &Session) -> bool {
     match File::open(src) {
         Ok(file) => {
+            let size = file.metadata().map(|meta| meta.len()).ok();
+            let mtime = file.metadata().and_then(|meta| meta.modified()).ok();
             let mut reader = BufReader::new(file);
 
-            match ftp_stream.put(desc, &mut reader) {
-                Ok(()) => (()),
-                Err(_) => println!("put file failed"),
+            if !session.quiet {
+                ftp_stream.set_progress(ProgressBar::new());
+            }
+            let result = match store {
+                Store::Unique => ftp_stream.put_unique(&mut reader).map(|name| {
+                    match name {
+                        Some(ref name) => println!("stored as {}", name),
+                        None => println!("stored under an unknown unique name"),
+                    }
+                    name
+                }),
+                _ => {
+                    let data_stream = match store {
+                        Store::Append => ftp_stream.append_stream(desc),
+                        _ => ftp_stream.put_stream(desc),
+                    };
+                    data_stream.and_then(|mut data_stream| {
+                        if let Some(size) = size {
+                            data_stream.set_total(size);
+                        }
+                        let copied = copy(&mut reader, &mut data_stream);
+                        try!(data_stream.finish());
+                        try!(copied);
+                        Ok(Some(desc.to_string()))
+                    })
+                }
+            };
+            ftp_stream.clear_progress();
+
+            match result {
+                Ok(name) => {
+                    if !session.quiet {
+                        verified(ftp_stream);
+                    }
+                    if let (true, Some(name), Some(mtime)) = (session.preserve_single(), name, mtime) {
+                        if ftp_stream.set_mdtm(&name, &to_utc(mtime)).is_err() {
+                            eprintln!("could not preserve modification time of {}", name);
+                        }
+                    }
+                    true
+                }
+                Err(FtpError::ChecksumMismatch(desc)) => {
+                    eprintln!("checksum mismatch: {}", desc);
+                    false
+                }
+                Err(_) => {
+                    eprintln!("put file failed");
+                    false
+                }
             }
         }
-        Err(err) => println!("open file failed: {}", err.to_string())
-    };
+        Err(err) => {
+            eprintln!("open file failed: {}", err.to_string());
+            false
+        }
+    }
+}
+
+/// Draws a progress bar while a file is transferred and prints a summary at the end
+struct ProgressBar {
+    tty: bool,
+    last_draw: Option<Instant>,
+}
+
+impl ProgressBar {
+    fn new() -> ProgressBar {
+        ProgressBar {
+            tty: stdio::stdout().is_terminal(),
+            last_draw: None,
+        }
+    }
+}
+
+impl ProgressObserver for ProgressBar {
+    fn update(&mut self, progress: &Progress) {
+        if !self.tty {
+            return;
+        }
+        match self.last_draw {
+            Some(last) if last.elapsed() < Duration::from_millis(100) => return,
+            _ => self.last_draw = Some(Instant::now()),
+        }
+
+        match progress.fraction() {
+            Some(fraction) => {
+                let width = 30;
+                let filled = (fraction * width as f64) as usize;
+                print!("\r\x1b[K[{}{}] {:3.0}% {} bytes {:.2} KB/s",
+                       "=".repeat(filled), " ".repeat(width - filled), fraction * 100.0,
+                       progress.bytes, progress.rate() / 1024.0);
+            }
+            None => print!("\r\x1b[K{} bytes {:.2} KB/s", progress.bytes, progress.rate() / 1024.0),
+        }
+        stdio::stdout().flush().unwrap();
+    }
+
+    fn finish(&mut self, progress: &Progress) {
+        if self.tty {
+            print!("\r\x1b[K");
+        }
+        let verb = match progress.direction {
+            Direction::Download => "received",
+            Direction::Upload => "sent",
+        };
+        println!("{} bytes {} in {:.2} secs ({:.2} KB/s)",
+                 progress.bytes, verb, progress.secs(), progress.rate() / 1024.0);
+    }
+}
+
+fn tree(ftp_stream: &mut FtpStream, pathname: &str, depth: Option<usize>, follow: bool) {
+    let mut walk = ftp_stream.walk(pathname).follow_symlinks(follow);
+    if let Some(depth) = depth {
+        walk = walk.max_depth(depth);
+    }
+
+    let (mut dirs, mut files) = (0, 0);
+    for item in walk {
+        let item = match item {
+            Ok(item) => item,
+            Err(_) => {
+                println!("tree command failed");
+                continue;
+            }
+        };
+        let indent = "    ".repeat(item.depth - 1);
+        match item.entry.target {
+            Some(ref target) => println!("{}{} -> {}", indent, item.entry.name, target),
+            None if item.entry.is_dir() => println!("{}{}/", indent, item.entry.name),
+            None => println!("{}{}", indent, item.entry.name),
+        }
+        if item.entry.is_dir() { dirs += 1 } else { files += 1 }
+    }
+    println!("{} directories, {} files", dirs, files);
+}
+
+fn rmdir_all(ftp_stream: &mut FtpStream, pathname: &str) {
+    if !confirm(&format!("remove {} and everything under it?", pathname)) {
+        return;
+    }
+    match ftp_stream.rmdir_all(pathname) {
+        Ok(()) => (()),
+        Err(_) => println!("rmdir command failed"),
+    }
+}
+
+/// Asks a yes or no question, anything but `y` or `yes` answers no
+fn confirm(question: &str) -> bool {
+    print!("{} [y/N] ", question);
+    stdio::stdout().flush().unwrap();
+
+    let mut answer = String::new();
+    match stdio::stdin().read_line(&mut answer) {
+        Ok(_) => {
+            let answer = answer.trim().to_lowercase();
+            answer == "y" || answer == "yes"
+        }
+        Err(_) => false,
+    }
 }
 
 fn size(ftp_stream: &mut FtpStream, filename: &str) {
@@ -346,26 +1118,706 @@ fn modtime(ftp_stream: &mut FtpStream, filename: &str) {
     }
 }
 
-fn main() {
-    let argc = env::args().count();
-    
-    match argc {
-        1 => {
-            println!("Usage: ftp <IP> [PORT]");
-            exit(-1);
+/// Parses the `-r` flag and the `-i`/`-x` include and exclude globs of `get` and `put`
+fn tree_args(args: &[String]) -> Option<(bool, Filter, Vec<String>)> {
+    let mut opts = filter_options();
+    opts.optflag("r", "recursive", "transfer a directory");
+
+    let matches = match opts.parse(args) {
+        Ok(matches) => matches,
+        Err(err) => {
+            println!("{}", err);
+            return None;
         }
-        _ => {
-            let mut args = env::args();
-            let ip = args.nth(1).unwrap();
+    };
+    Some((matches.opt_present("r"), filter(&matches), matches.free))
+}
+
+/// Options taking the include and exclude globs of recursive transfers
+fn filter_options() -> Options {
+    let mut opts = Options::new();
+    opts.optmulti("i", "include", "only transfer files matching", "GLOB");
+    opts.optmulti("x", "exclude", "skip files and directories matching", "GLOB");
+    opts
+}
+
+/// Syncs a remote directory and a local one both ways
+fn sync(ftp_stream: &mut FtpStream, session: &Session, args: &[String]) {
+    let mut opts = filter_options();
+    opts.optflag("n", "dry-run", "only show what would be done");
+    opts.optopt("p", "policy", "how to settle conflicts", "newer|both|ask");
+
+    let matches = match opts.parse(args) {
+        Ok(matches) => matches,
+        Err(err) => {
+            println!("{}", err);
+            return;
+        }
+    };
+    let policy = match matches.opt_str("p").as_ref().map(|policy| &policy[..]) {
+        None | Some("newer") => Policy::Newer,
+        Some("both") => Policy::KeepBoth,
+        Some("ask") => Policy::Ask,
+        Some(policy) => {
+            println!("unknown conflict policy {}", policy);
+            return;
+        }
+    };
+    let remote = match matches.free.get(0) {
+        Some(remote) => remote.clone(),
+        None => {
+            println!("Invalid arguements");
+            return;
+        }
+    };
+    let local = matches.free.get(1).cloned().unwrap_or(basename(&remote).to_string());
+
+    let sync = TwoWaySync::new(&local, &remote).filter(filter(&matches)).policy(policy);
+    let plan = match sync.plan(ftp_stream, ask_conflict) {
+        Ok(plan) => plan,
+        Err(err) => {
+            println!("sync command failed: {}", err);
+            return;
+        }
+    };
+    for conflict in &plan.conflicts {
+        println!("conflict: {} changed on both sides", conflict.path);
+    }
+    if matches.opt_present("n") {
+        for action in &plan.actions {
+            println!("{}", action);
+        }
+        println!("{} actions, {} transfers", plan.actions.len(), plan.jobs().len());
+        return;
+    }
+
+    let started = Instant::now();
+    let engine = match engine(ftp_stream, session, plan.jobs().len(), started) {
+        Some(engine) => engine.preserve(true),
+        None => return,
+    };
+    // the listings taken after the transfers would garble the progress line
+    ftp_stream.set_verbose(false);
+    let outcome = plan.execute(ftp_stream, &engine);
+    ftp_stream.set_verbose(true);
+    let outcome = match outcome {
+        Ok(outcome) => outcome,
+        Err(err) => {
+            println!("sync command failed: {}", err);
+            return;
+        }
+    };
+    if outcome.results.len() > 0 {
+        print!("\n");
+        report(&outcome.results, started);
+    }
+    for &(ref action, ref err) in &outcome.errors {
+        println!("failed  {} ({})", action, err);
+    }
+    if plan.actions.len() == 0 {
+        println!("{} and {} are in sync", remote, local);
+    }
+}
+
+fn ask_conflict(conflict: &Conflict) -> Resolution {
+    let show = |stamp: Option<Stamp>| stamp.map_or("deleted".to_string(), |stamp| stamp.to_string());
+    println!("{} changed on both sides", conflict.path);
+    println!("  local:  {}", show(conflict.local));
+    println!("  remote: {}", show(conflict.remote));
+    loop {
+        print!("keep [l]ocal, [r]emote, [b]oth or [s]kip? ");
+        stdio::stdout().flush().unwrap();
+
+        let mut answer = String::new();
+        if stdio::stdin().read_line(&mut answer).unwrap_or(0) == 0 {
+            return Resolution::Skip;
+        }
+        match answer.trim() {
+            "l" | "local" => return Resolution::Local,
+            "r" | "remote" => return Resolution::Remote,
+            "b" | "both" => return Resolution::KeepBoth,
+            "s" | "skip" => return Resolution::Skip,
+            _ => (),
+        }
+    }
+}
+
+fn filter(matches: &Matches) -> Filter {
+    let filter = matches.opt_strs("x").iter().fold(Filter::new(), |filter, glob| filter.exclude(glob));
+    matches.opt_strs("i").iter().fold(filter, |filter, glob| filter.include(glob))
+}
+
+/// Mirrors a remote directory into a local one, or the other way around with `-R`
+fn mirror(ftp_stream: &mut FtpStream, session: &Session, args: &[String]) {
+    let mut opts = filter_options();
+    opts.optflag("R", "reverse", "mirror a local directory to the server");
+    opts.optflag("n", "dry-run", "only show what would be done");
+    opts.optflag("d", "delete", "delete files missing from the source");
+    opts.optopt("c", "checksum", "compare files by checksum", "ALGORITHM");
+
+    let matches = match opts.parse(args) {
+        Ok(matches) => matches,
+        Err(err) => {
+            println!("{}", err);
+            return;
+        }
+    };
+    let checksum = match matches.opt_str("c").map(|algorithm| algorithm.parse::<Algorithm>()) {
+        Some(Err(err)) => {
+            println!("{}", err);
+            return;
+        }
+        Some(Ok(algorithm)) => Some(algorithm),
+        None => None,
+    };
+    let src = match matches.free.get(0) {
+        Some(src) => src.clone(),
+        None => {
+            println!("Invalid arguements");
+            return;
+        }
+    };
+    let desc = matches.free.get(1).cloned().unwrap_or(basename(&src).to_string());
+
+    let mirror = if matches.opt_present("R") {
+        Mirror::upload(&src, &desc)
+    } else {
+        Mirror::download(&src, &desc)
+    };
+    let mirror = mirror.filter(filter(&matches))
+        .delete(matches.opt_present("d"))
+        .checksum(checksum);
+
+    let plan = match mirror.plan(ftp_stream) {
+        Ok(plan) => plan,
+        Err(err) => {
+            println!("mirror command failed: {}", err);
+            return;
+        }
+    };
+    if plan.actions.len() == 0 {
+        println!("{} is up to date", desc);
+        return;
+    }
+    if matches.opt_present("n") {
+        for action in &plan.actions {
+            println!("{}", action);
+        }
+        println!("{} actions, {} transfers", plan.actions.len(), plan.jobs().len());
+        return;
+    }
+
+    let started = Instant::now();
+    let engine = match engine(ftp_stream, session, plan.jobs().len(), started) {
+        Some(engine) => engine.preserve(true),
+        None => return,
+    };
+    let outcome = plan.execute(ftp_stream, &engine);
+    if outcome.results.len() > 0 {
+        print!("\n");
+        report(&outcome.results, started);
+    }
+    for &(ref action, ref err) in &outcome.errors {
+        println!("failed  {} ({})", action, err);
+    }
+}
+
+/// Retrives the remote directory `src` into `desc`, creating the local directories
+fn get_tree(ftp_stream: &mut FtpStream, session: &Session, src: &str, desc: &str, filter: &Filter) {
+    let mut jobs = Vec::new();
+    let (mut dirs, mut errors) = (0, 0);
+    if let Err(err) = fs::create_dir_all(desc) {
+        println!("cannot create {}: {}", desc, err);
+        return;
+    }
+
+    for item in ftp_stream.walk(src) {
+        let item = match item {
+            Ok(item) => item,
+            Err(err) => {
+                println!("listing failed: {}", err);
+                errors += 1;
+                continue;
+            }
+        };
+        if !filter.matches(&item.relative, item.entry.is_dir()) {
+            continue;
+        }
+        let local = match local_path(desc, &item.relative) {
+            Some(local) => local,
+            None => {
+                println!("skipping {}, it would land outside {}", item.path, desc);
+                continue;
+            }
+        };
+        if item.entry.is_dir() {
+            match fs::create_dir_all(&local) {
+                Ok(()) => dirs += 1,
+                Err(err) => {
+                    println!("cannot create {}: {}", local.display(), err);
+                    errors += 1;
+                }
+            }
+        } else if item.entry.is_file() {
+            jobs.push(Job::get(&item.path, &local.to_string_lossy()));
+        }
+    }
+
+    println!("{} directories, {} files to retrive", dirs, jobs.len());
+    if jobs.len() > 0 {
+        transfer(ftp_stream, session, jobs);
+    }
+    if errors > 0 {
+        println!("{} directories could not be listed or created", errors);
+    }
+}
+
+/// Sends the local directory `src` to `desc`, creating the remote directories
+fn put_tree(ftp_stream: &mut FtpStream, session: &Session, src: &str, desc: &str, filter: &Filter) {
+    let entries = match walk_local(src) {
+        Ok(entries) => entries,
+        Err(err) => {
+            println!("cannot read {}: {}", src, err);
+            return;
+        }
+    };
+    if ftp_stream.mkdir_all(desc).is_err() {
+        println!("mkdir command failed");
+        return;
+    }
+
+    let mut jobs = Vec::new();
+    let (mut dirs, mut errors) = (0, 0);
+    for item in entries {
+        if !filter.matches(&item.relative, item.entry.is_dir()) {
+            continue;
+        }
+        let remote = join(desc, &item.relative);
+        if item.entry.is_dir() {
+            // parents come first, so only the directory itself can be missing
+            let created = ftp_stream.mkdir(&remote).is_ok() || ftp_stream.is_dir(&remote).unwrap_or(false);
+            if created {
+                dirs += 1;
+            } else {
+                println!("cannot create {}", remote);
+                errors += 1;
+            }
+        } else if item.entry.is_file() {
+            jobs.push(Job::put(&item.path, &remote));
+        }
+    }
+
+    println!("{} directories, {} files to send", dirs, jobs.len());
+    if jobs.len() > 0 {
+        transfer(ftp_stream, session, jobs);
+    }
+    if errors > 0 {
+        println!("{} directories could not be created", errors);
+    }
+}
+
+/// Expands the arguments holding wildcards into the remote files they match
+fn remote_glob(ftp_stream: &mut FtpStream, args: &[String]) -> Vec<String> {
+    let mut paths = Vec::new();
+    for arg in args {
+        if !is_pattern(arg) {
+            paths.push(arg.clone());
+            continue;
+        }
+        match ftp_stream.glob(arg) {
+            Ok(ref matches) if matches.len() == 0 => println!("{}: no match", arg),
+            Ok(matches) => paths.extend(matches),
+            Err(_) => println!("{}: listing failed", arg),
+        }
+    }
+    paths
+}
+
+/// Expands the arguments holding wildcards into the local files they match
+fn local_glob(args: &[String]) -> Vec<String> {
+    let mut paths = Vec::new();
+    for arg in args {
+        if !is_pattern(arg) {
+            paths.push(arg.clone());
+            continue;
+        }
+        match glob_local(arg) {
+            Ok(ref matches) if matches.len() == 0 => println!("{}: no match", arg),
+            Ok(matches) => paths.extend(matches),
+            Err(err) => println!("{}: {}", arg, err),
+        }
+    }
+    paths
+}
+
+/// Asks about each path when prompting is on, `a` takes all the remaining ones and `q` none
+fn choose(session: &Session, verb: &str, paths: Vec<String>) -> Vec<String> {
+    if !session.prompt {
+        return paths;
+    }
+
+    let mut chosen = Vec::new();
+    let mut paths = paths.into_iter();
+    while let Some(path) = paths.next() {
+        print!("{} {}? [y/n/a/q] ", verb, path);
+        stdio::stdout().flush().unwrap();
+
+        let mut answer = String::new();
+        if stdio::stdin().read_line(&mut answer).unwrap_or(0) == 0 {
+            break;
+        }
+        match answer.trim().to_lowercase().as_ref() {
+            "y" | "yes" | "" => chosen.push(path),
+            "a" | "all" => {
+                chosen.push(path);
+                chosen.extend(paths);
+                break;
+            }
+            "q" | "quit" => break,
+            _ => (),
+        }
+    }
+    chosen
+}
+
+fn basename(path: &str) -> &str {
+    Path::new(path).file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(path)
+}
+
+fn transfer(ftp_stream: &mut FtpStream, session: &Session, jobs: Vec<Job>) {
+    let started = Instant::now();
+    let engine = match engine(ftp_stream, session, jobs.len(), started) {
+        Some(engine) => engine,
+        None => return,
+    };
+
+    let results = engine.run(jobs);
+    print!("\n");
+    report(&results, started);
+}
+
+/// Builds an engine running `total` jobs with the session's settings and a progress display
+fn engine(ftp_stream: &mut FtpStream, session: &Session, total: usize, started: Instant) -> Option<TransferEngine> {
+    // worker connections start in the login directory, follow the current one
+    let cwd = match ftp_stream.pwd() {
+        Ok(cwd) => cwd,
+        Err(_) => {
+            println!("pwd command error");
+            return None;
+        }
+    };
+    let addr = session.addr.clone();
+    let credentials = session.credentials.clone();
+    let verify = session.verify;
+    let compress = session.compress;
+    let passive = session.passive;
+    let timeout = session.timeout;
+    let display = Mutex::new(BatchDisplay {
+        done: 0,
+        done_bytes: 0,
+        running: HashMap::new(),
+        last_draw: started,
+    });
+
+    let engine = TransferEngine::new(move || {
+        let mut stream = try!(FtpStream::connect_quiet(&addr[..]));
+        stream.set_passive(passive);
+        try!(stream.set_timeout(timeout));
+        if let Some(ref credentials) = credentials {
+            let account = credentials.account.as_ref().map(|account| &account[..]);
+            try!(stream.login_account(&credentials.user, &credentials.password, account));
+        }
+        try!(stream.cwd(&cwd));
+        stream.set_checksum(verify);
+        stream.set_verify(verify.is_some());
+        if compress {
+            try!(stream.set_compression(true));
+        }
+        Ok(stream)
+    }).workers(session.workers)
+        .limit(Some(session.download_limit.clone()), Some(session.upload_limit.clone()))
+        .preserve(session.preserve_batch())
+        .observe(move |event| {
+        let mut display = display.lock().unwrap();
+        match *event {
+            Event::Started { .. } => return,
+            Event::Progress { index, progress, .. } => {
+                display.running.insert(index, progress.bytes);
+                if display.last_draw.elapsed() < Duration::from_millis(100) {
+                    return;
+                }
+            }
+            Event::Finished { result, done, .. } => {
+                display.running.remove(&result.index);
+                display.done_bytes += result.bytes;
+                display.done = done;
+            }
+        }
+        display.last_draw = Instant::now();
+
+        let bytes = display.done_bytes + display.running.values().sum::<u64>();
+        let secs = started.elapsed().as_secs() as f64 + started.elapsed().subsec_nanos() as f64 / 1e9;
+        print!("\r\x1b[K[{}/{}] {} bytes {:.2} KB/s", display.done, total, bytes, bytes as f64 / secs / 1024.0);
+        stdio::stdout().flush().unwrap();
+    });
+
+    Some(engine)
+}
+
+/// Shared progress of the jobs run by `transfer`
+struct BatchDisplay {
+    done: usize,
+    done_bytes: u64,
+    running: HashMap<usize, u64>,
+    last_draw: Instant,
+}
+
+fn report(results: &[JobResult], started: Instant) {
+    let (mut failed, mut bytes) = (0, 0);
+    for result in results {
+        let (verb, from, to) = match result.job {
+            Job::Get { ref remote, ref local } => ("get", remote, local),
+            Job::Put { ref local, ref remote } => ("put", local, remote),
+        };
+        match result.result {
+            Ok(()) => {
+                bytes += result.bytes;
+                println!("ok      {} {} -> {} ({} bytes)", verb, from, to, result.bytes);
+            }
+            Err(ref err) => {
+                failed += 1;
+                println!("failed  {} {} -> {} ({})", verb, from, to, err);
+            }
+        }
+    }
+    let elapsed = started.elapsed();
+    let secs = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1e9;
+    println!("{} succeeded, {} failed, {} bytes transferred in {:.2} secs ({:.2} KB/s)",
+             results.len() - failed, failed, bytes, secs, bytes as f64 / secs / 1024.0);
+}
+
+/// Shows the checksum of the last transfer, only computed when `verify` is on
+fn verified(ftp_stream: &FtpStream) {
+    if let Some(sum) = ftp_stream.last_checksum() {
+        println!("{} verified: {}", sum.algorithm, sum.value);
+    }
+}
+
+fn touch(ftp_stream: &mut FtpStream, filename: &str, time: Option<&str>) {
+    let time = match time {
+        Some(time) => match UTC.datetime_from_str(time, "%Y%m%d%H%M%S") {
+            Ok(time) => time,
+            Err(_) => {
+                println!("Invalid time {}, expected YYYYMMDDHHMMSS", time);
+                return;
+            }
+        },
+        None => UTC::now(),
+    };
+
+    match ftp_stream.set_mdtm(filename, &time) {
+        Ok(()) => (()),
+        Err(_) => println!("touch command failed"),
+    }
+}
+
+/// Copies a remote file straight to another server, `fxp <source> <host[:port]> [dest]`
+fn fxp(ftp_stream: &mut FtpStream, session: &Session, args: &[String]) {
+    if args.len() < 2 {
+        println!("Invalid arguements");
+        return;
+    }
+    let source = &args[0];
+    let dest = args.get(2).unwrap_or(source);
+    let addr = if args[1].contains(':') { args[1].clone() } else { format!("{}:21", args[1]) };
+
+    let mut other = match FtpStream::connect(&addr[..]) {
+        Ok(other) => other,
+        Err(err) => {
+            println!("Connection Failed: {}", err);
+            return;
+        }
+    };
+    if ask_login(&mut other, None).is_none() {
+        return;
+    }
+    if session.compress {
+        // both sides must agree on the mode, the data isn't inflated in between
+        match other.set_compression(true) {
+            Ok(true) => (),
+            _ => {
+                println!("{} does not support MODE Z, turn compress off first", args[1]);
+                let _ = other.quit();
+                return;
+            }
+        }
+    }
+
+    let started = Instant::now();
+    match ftp_stream.fxp(source, &mut other, dest) {
+        Ok(()) => {
+            let elapsed = started.elapsed();
+            let secs = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1e9;
+            println!("{} copied to {}:{} in {:.2} secs", source, args[1], dest, secs);
+        }
+        Err(err) => println!("fxp command failed: {}", err),
+    }
+    let _ = other.quit();
+}
+
+fn checksum(ftp_stream: &mut FtpStream, algorithm: &str, remote: &str, local: Option<&str>) {
+    let algorithm = match algorithm.parse::<Algorithm>() {
+        Ok(algorithm) => algorithm,
+        Err(err) => {
+            println!("{}", err);
+            return;
+        }
+    };
+
+    let remote_sum = match ftp_stream.remote_checksum(remote, algorithm) {
+        Ok(sum) => sum,
+        Err(_) => {
+            println!("checksum command failed");
+            return;
+        }
+    };
+    println!("{} {}: {}", algorithm, remote, remote_sum);
+
+    if let Some(local) = local {
+        match file_checksum(local, algorithm) {
+            Ok(local_sum) => {
+                let verdict = if local_sum == remote_sum { "match" } else { "MISMATCH" };
+                println!("{} {}: {} ({})", algorithm, local, local_sum, verdict);
+            }
+            Err(err) => println!("open file failed: {}", err.to_string()),
+        }
+    }
+}
+
+fn rate(session: &Session, args: &[String]) {
+    let (limiters, value) = match args.len() {
+        0 => {
+            println!("get: {}, put: {}", show_rate(session.download_limit.rate()),
+                     show_rate(session.upload_limit.rate()));
+            return;
+        }
+        1 => (vec![&session.download_limit, &session.upload_limit], &args[0]),
+        _ => match args[0].as_ref() {
+            "get" => (vec![&session.download_limit], &args[1]),
+            "put" => (vec![&session.upload_limit], &args[1]),
+            _ => {
+                println!("Invalid arguements");
+                return;
+            }
+        },
+    };
+
+    if !set_rate(&limiters, value) {
+        println!("Invalid rate {}", value);
+    }
+}
+
+fn set_rate(limiters: &[&RateLimiter], value: &str) -> bool {
+    match parse_rate(value) {
+        Some(rate) => {
+            for limiter in limiters {
+                limiter.set_rate(rate);
+            }
+            true
+        }
+        None => false,
+    }
+}
+
+fn show_rate(rate: u64) -> String {
+    match rate {
+        0 => "unlimited".to_string(),
+        _ => format!("{} bytes/s", rate),
+    }
+}
+
+/// Exit statuses of the one-shot commands like `zftp get <url>`
+const EXIT_FAILED: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+const EXIT_CONNECT: i32 = 3;
+const EXIT_LOGIN: i32 = 4;
+
+/// The commands run once from the command line instead of the prompt
+const ONE_SHOT: [&'static str; 5] = ["get", "put", "ls", "rm", "mkdir"];
+
+fn main() {
+    let mut opts = Options::new();
+    opts.optopt("", "rate", "limit transfer rate, e.g. 500K", "RATE");
+    opts.optopt("", "rate-get", "limit download rate", "RATE");
+    opts.optopt("", "rate-put", "limit upload rate", "RATE");
+    opts.optflag("n", "", "don't log in after connecting");
+    opts.optopt("u", "user", "log in as USER, only asking for the password", "USER");
+    opts.optflag("a", "anonymous", "log in anonymously");
+    opts.optopt("", "email", "password of anonymous logins, anonymous@ by default", "EMAIL");
+    opts.optflag("p", "parents", "make missing parent directories with mkdir");
+    opts.optflag("v", "verbose", "show the server replies of get, put, ls, rm and mkdir");
+
+    let args: Vec<String> = env::args().collect();
+    let matches = match opts.parse(&args[1..]) {
+        Ok(matches) => matches,
+        Err(err) => {
+            println!("{}", err);
+            exit(-1);
+        }
+    };
+    
+    match matches.free.len() {
+        0 => {
+            print!("{}", opts.usage("Usage: zftp [options] <PROFILE|HOST|URL> [PORT]\n       \
+                                     zftp [options] get <URL> [LOCAL] | put <LOCAL> <URL> | ls|rm|mkdir <URL>"));
+            exit(-1);
+        }
+        _ if ONE_SHOT.contains(&&matches.free[0][..]) => exit(run_once(&matches)),
+        _ => {
+            let config = match load_config() {
+                Ok(config) => config,
+                Err(err) => {
+                    println!("{}", err);
+                    exit(-1);
+                }
+            };
+            let mut args = matches.free.iter().cloned();
+            let name = args.next().unwrap();
+            let (profile, url) = match host_profile(&config, &name) {
+                Ok(found) => found,
+                Err(err) => {
+                    println!("{}", err);
+                    exit(-1);
+                }
+            };
+            let host = profile.host.clone().unwrap();
             let port = match args.next() {
                 Some(port) => port,
-                None => "21".to_string(),
+                None => profile.port.unwrap_or(21).to_string(),
             };
-            let addr = format!("{}:{}", ip, port);
-            match FtpStream::connect(&addr[..]) {
+            let mut session = Session::new(&address(&host, &port));
+            if let Err(err) = configure(&mut session, &profile, &matches) {
+                println!("{}", err);
+                exit(-1);
+            }
+            session.config = config;
+
+            match FtpStream::connect(&session.addr[..]) {
                 Ok(mut stream) => {
-                    println!("Connected to {}", ip);
-                    cmd_loop(&mut stream)
+                    println!("Connected to {}", host);
+                    prepare(&mut stream, &session);
+                    if !matches.opt_present("n") {
+                        auto_login(&mut stream, &mut session, &matches, &profile, url.as_ref());
+                        if session.credentials.is_some() {
+                            setup(&mut stream, &mut session, &profile);
+                            if let Some(ref url) = url {
+                                url_cd(&mut stream, url);
+                            }
+                        }
+                    }
+                    cmd_loop(&mut stream, &mut session)
                 }
                 Err(err) => {
                     println!("Connection Failed: {}", err.to_string());
@@ -375,3 +1827,232 @@ fn main() {
         }
     }
 }
+
+/// Runs a command given on the command line, e.g. `zftp get ftp://host/file.zip ./out/`, and
+/// returns the exit status
+fn run_once(matches: &Matches) -> i32 {
+    let command = &matches.free[0][..];
+    let args = &matches.free[1..];
+    let (target, local) = match (command, args.len()) {
+        ("get", 1) | ("get", 2) => (&args[0], args.get(1)),
+        ("put", 2) => (&args[1], Some(&args[0])),
+        ("ls", 1) | ("rm", 1) | ("mkdir", 1) => (&args[0], None),
+        _ => {
+            eprintln!("Invalid arguements, see zftp without arguments for the usage");
+            return EXIT_USAGE;
+        }
+    };
+    if !FtpUrl::is_url(target) {
+        eprintln!("{} is not a URL like ftp://host/path", target);
+        return EXIT_USAGE;
+    }
+    if matches.opt_present("n") {
+        eprintln!("-n can't be used with {}, which needs to log in", command);
+        return EXIT_USAGE;
+    }
+    let (profile, url) = match load_config().and_then(|config| host_profile(&config, target)) {
+        Ok((profile, url)) => (profile, url.unwrap()),
+        Err(err) => {
+            eprintln!("{}", err);
+            return EXIT_USAGE;
+        }
+    };
+
+    let mut session = Session::new(&url.addr());
+    session.quiet = !matches.opt_present("verbose");
+    if let Err(err) = configure(&mut session, &profile, matches) {
+        eprintln!("{}", err);
+        return EXIT_USAGE;
+    }
+    let mut stream = match FtpStream::connect_quiet(&session.addr[..]) {
+        Ok(stream) => stream,
+        Err(err) => {
+            eprintln!("Connection Failed: {}", err);
+            return EXIT_CONNECT;
+        }
+    };
+    stream.set_verbose(!session.quiet);
+    prepare(&mut stream, &session);
+    auto_login(&mut stream, &mut session, matches, &profile, Some(&url));
+    if session.credentials.is_none() {
+        return EXIT_LOGIN;
+    }
+    setup(&mut stream, &mut session, &profile);
+
+    let path = &url.path[..];
+    let done = match command {
+        "get" => {
+            if url.file_name().is_empty() {
+                eprintln!("{} does not name a file", target);
+                return EXIT_USAGE;
+            }
+            match local_target(local.map(|local| &local[..]), url.file_name()) {
+                Ok(desc) => get(&mut stream, path, &desc, &session),
+                Err(err) => {
+                    eprintln!("{}", err);
+                    false
+                }
+            }
+        }
+        "put" => {
+            let src = &local.unwrap()[..];
+            // a directory URL keeps the local name
+            let desc = match url.file_name() {
+                "" => format!("{}{}", path, basename(src)),
+                _ => path.to_string(),
+            };
+            put(&mut stream, src, &desc, Store::Replace, &session)
+        }
+        "ls" => ls(&mut stream, if path.is_empty() { None } else { Some(path) }),
+        "rm" => match stream.rm(path) {
+            Ok(()) => true,
+            Err(err) => {
+                eprintln!("delete command failed: {}", err);
+                false
+            }
+        },
+        _ => {
+            let dir = path.trim_end_matches('/');
+            let result = if matches.opt_present("parents") { stream.mkdir_all(dir) } else { stream.mkdir(dir) };
+            match result {
+                Ok(()) => true,
+                Err(err) => {
+                    eprintln!("mkdir command failed: {}", err);
+                    false
+                }
+            }
+        }
+    };
+    let _ = stream.quit();
+    if done { 0 } else { EXIT_FAILED }
+}
+
+/// The local file a download of `name` goes to: `local` itself, or `name` in it when it is
+/// a directory. A missing directory ending with `/` is created.
+fn local_target(local: Option<&str>, name: &str) -> stdio::Result<String> {
+    let local = match local {
+        Some(local) => local,
+        None => return Ok(name.to_string()),
+    };
+    if local.ends_with('/') {
+        try!(fs::create_dir_all(local));
+    }
+    if Path::new(local).is_dir() {
+        Ok(Path::new(local).join(name).to_string_lossy().into_owned())
+    } else {
+        Ok(local.to_string())
+    }
+}
+
+fn address(host: &str, port: &str) -> String {
+    if host.contains(':') {
+        format!("[{}]:{}", host, port)
+    } else {
+        format!("{}:{}", host, port)
+    }
+}
+
+/// Takes the settings of the profile, and the rates given on the command line
+fn configure(session: &mut Session, profile: &Profile, matches: &Matches) -> ::std::result::Result<(), String> {
+    session.passive = profile.passive.unwrap_or(true);
+    session.timeout = profile.timeout;
+    if let Some(parallel) = profile.parallel {
+        session.workers = parallel;
+    }
+    if let Some(rate) = profile.rate {
+        session.download_limit.set_rate(rate);
+        session.upload_limit.set_rate(rate);
+    }
+    let rates = [
+        ("rate", vec![&session.download_limit, &session.upload_limit]),
+        ("rate-get", vec![&session.download_limit]),
+        ("rate-put", vec![&session.upload_limit]),
+    ];
+    for &(name, ref limiters) in rates.iter() {
+        if let Some(value) = matches.opt_str(name) {
+            if !set_rate(limiters, &value) {
+                return Err(format!("Invalid rate {}", value));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Applies the session's settings to a new connection
+fn prepare(ftp_stream: &mut FtpStream, session: &Session) {
+    ftp_stream.set_download_limit(Some(session.download_limit.clone()));
+    ftp_stream.set_upload_limit(Some(session.upload_limit.clone()));
+    ftp_stream.set_passive(session.passive);
+    if let Err(err) = ftp_stream.set_timeout(session.timeout) {
+        eprintln!("Invalid timeout: {}", err);
+    }
+}
+
+/// Reads the configuration file if there is one
+fn load_config() -> ::std::result::Result<Config, String> {
+    let path = match Config::default_path() {
+        Some(path) => path,
+        None => return Ok(Config::default()),
+    };
+    match Config::load(&path) {
+        Ok(config) => Ok(config),
+        Err(ref err) if err.kind() == stdio::ErrorKind::NotFound => Ok(Config::default()),
+        Err(err) => Err(format!("{}: {}", path.display(), err)),
+    }
+}
+
+/// The profile called `name`, or the defaults for the host or the URL `name`, failing if it
+/// asks for something zftp can't do
+fn host_profile(config: &Config, name: &str) -> ::std::result::Result<(Profile, Option<FtpUrl>), String> {
+    let mut url = None;
+    let profile = if FtpUrl::is_url(name) {
+        let parsed: FtpUrl = try!(name.parse().map_err(|err| format!("Invalid URL: {}", err)));
+        if parsed.tls != Tls::None {
+            return Err(format!("{} TLS is not supported", parsed.tls));
+        }
+        let profile = Profile {
+            host: Some(parsed.host.clone()),
+            port: Some(parsed.port),
+            user: parsed.user.clone(),
+            password: parsed.password.clone(),
+            ..Profile::default()
+        };
+        url = Some(parsed);
+        profile.or(&config.defaults)
+    } else {
+        match config.profile(name) {
+            Some(profile) => profile,
+            None => Profile { host: Some(name.to_string()), ..Profile::default() }.or(&config.defaults),
+        }
+    };
+    if profile.host.is_none() {
+        return Err(format!("Profile {} has no host", name));
+    }
+    Ok((profile, url))
+}
+
+fn profiles(config: &Config) {
+    if config.profiles.is_empty() {
+        println!("No profiles configured");
+        return;
+    }
+    for name in config.profiles.keys() {
+        let profile = config.profile(name).unwrap();
+        let user = match (profile.anonymous, profile.user) {
+            (Some(true), _) => format!("{}@", ANONYMOUS_USER),
+            (_, Some(user)) => format!("{}@", user),
+            _ => String::new(),
+        };
+        let mut notes = Vec::new();
+        if profile.passive == Some(false) {
+            notes.push(String::from("active"));
+        }
+        if let Some(timeout) = profile.timeout {
+            notes.push(format!("timeout {}s", timeout.as_secs()));
+        }
+        let notes = if notes.is_empty() { String::new() } else { format!(" ({})", notes.join(", ")) };
+        let dir = profile.dir.map(|dir| format!(" {}", dir)).unwrap_or_default();
+        println!("{:<20} {}{}:{}{}{}", name, user, profile.host.unwrap_or_default(), profile.port.unwrap_or(21),
+                 dir, notes);
+    }
+}
@@ -0,0 +1,340 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use chrono::{DateTime, UTC};
+use super::ftp::FtpStream;
+use super::engine::{TransferEngine, Job, JobResult};
+use super::progress::Direction;
+use super::checksum::{Algorithm, file_checksum};
+use super::glob::Filter;
+use super::listing::Entry;
+use super::walk::{join, local_path, walk_local, WalkEntry};
+use super::types::{FtpError, Result};
+
+/// Why a file is transferred again
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    /// The destination has no such file
+    New,
+    Size,
+    Modified,
+    Checksum,
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let desc = match *self {
+            Change::New => "new",
+            Change::Size => "size differs",
+            Change::Modified => "modification time differs",
+            Change::Checksum => "checksum differs",
+        };
+        write!(f, "{}", desc)
+    }
+}
+
+/// A step of a mirror `Plan`, paths are on the destination side unless said otherwise
+#[derive(Debug, Clone)]
+pub enum Action {
+    MakeDir(String),
+    Transfer { job: Job, change: Change },
+    /// Removes a file, or a directory with everything under it
+    Delete { path: String, dir: bool },
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Action::MakeDir(ref path) => write!(f, "mkdir   {}", path),
+            Action::Transfer { job: Job::Get { ref remote, ref local }, change } => {
+                write!(f, "get     {} -> {} ({})", remote, local, change)
+            }
+            Action::Transfer { job: Job::Put { ref local, ref remote }, change } => {
+                write!(f, "put     {} -> {} ({})", local, remote, change)
+            }
+            Action::Delete { ref path, dir } => {
+                write!(f, "delete  {}{}", path, if dir { "/" } else { "" })
+            }
+        }
+    }
+}
+
+/// Makes a destination tree identical to a source tree, see `Mirror::plan`
+///
+/// Files are compared by size and modification time, or by size and checksum when a
+/// checksum algorithm is set. The modification times only stay comparable when the
+/// transfers preserve them, which `TransferEngine` does by default.
+#[derive(Debug, Clone)]
+pub struct Mirror {
+    direction: Direction,
+    local: String,
+    remote: String,
+    filter: Filter,
+    delete: bool,
+    checksum: Option<Algorithm>,
+}
+
+impl Mirror {
+    /// Mirrors the remote directory `remote` into the local directory `local`
+    pub fn download(remote: &str, local: &str) -> Mirror {
+        Mirror::new(Direction::Download, local, remote)
+    }
+
+    /// Mirrors the local directory `local` into the remote directory `remote`
+    pub fn upload(local: &str, remote: &str) -> Mirror {
+        Mirror::new(Direction::Upload, local, remote)
+    }
+
+    fn new(direction: Direction, local: &str, remote: &str) -> Mirror {
+        Mirror {
+            direction: direction,
+            local: local.to_string(),
+            remote: remote.to_string(),
+            filter: Filter::new(),
+            delete: false,
+            checksum: None,
+        }
+    }
+
+    /// Restricts the mirror to the matching entries, excluded destination entries are never deleted
+    pub fn filter(mut self, filter: Filter) -> Mirror {
+        self.filter = filter;
+        self
+    }
+
+    /// Deletes the destination entries missing from the source, off by default
+    pub fn delete(mut self, delete: bool) -> Mirror {
+        self.delete = delete;
+        self
+    }
+
+    /// Compares files of the same size by checksum instead of modification time
+    pub fn checksum(mut self, algorithm: Option<Algorithm>) -> Mirror {
+        self.checksum = algorithm;
+        self
+    }
+
+    /// Compares both trees and lists what has to be done, without changing anything
+    pub fn plan(&self, ftp: &mut FtpStream) -> Result<Plan> {
+        let remote = try!(self.remote_entries(ftp));
+        let local = if Path::new(&self.local).is_dir() {
+            Some(try!(walk_local(&self.local)))
+        } else {
+            None
+        };
+        let (source, dest) = match self.direction {
+            Direction::Download => (remote, local),
+            Direction::Upload => (local, remote),
+        };
+        let source = match source {
+            Some(source) => self.filtered(source),
+            None => {
+                let root = match self.direction {
+                    Direction::Download => &self.remote,
+                    Direction::Upload => &self.local,
+                };
+                return Err(FtpError::InvalidResponse(format!("{} is not a directory", root)));
+            }
+        };
+
+        let mut actions = Vec::new();
+        let dest = match dest {
+            Some(dest) => self.filtered(dest),
+            None => {
+                actions.push(Action::MakeDir(self.dest_path("")));
+                Vec::new()
+            }
+        };
+        let dest_entries: HashMap<&str, &Entry> = dest.iter()
+            .map(|item| (&item.relative[..], &item.entry)).collect();
+        // directories deleted along with everything under them
+        let mut deleted: HashSet<&str> = HashSet::new();
+
+        for item in &source {
+            let existing = dest_entries.get(&item.relative[..]).cloned();
+            if item.entry.is_dir() {
+                match existing {
+                    Some(entry) if entry.is_dir() => (),
+                    Some(_) if !self.delete => continue,
+                    Some(_) => {
+                        actions.push(Action::Delete { path: self.dest_path(&item.relative), dir: false });
+                        actions.push(Action::MakeDir(self.dest_path(&item.relative)));
+                    }
+                    None => actions.push(Action::MakeDir(self.dest_path(&item.relative))),
+                }
+            } else if item.entry.is_file() {
+                let change = match existing {
+                    Some(entry) if entry.is_file() => try!(self.compare(ftp, item, entry)),
+                    Some(_) if !self.delete => continue,
+                    Some(entry) => {
+                        actions.push(Action::Delete { path: self.dest_path(&item.relative), dir: entry.is_dir() });
+                        if entry.is_dir() {
+                            deleted.insert(&item.relative);
+                        }
+                        Some(Change::New)
+                    }
+                    None => Some(Change::New),
+                };
+                if let Some(change) = change {
+                    actions.push(Action::Transfer { job: self.job(&item.relative), change: change });
+                }
+            }
+        }
+
+        if self.delete {
+            let source_entries: HashMap<&str, &Entry> = source.iter()
+                .map(|item| (&item.relative[..], &item.entry)).collect();
+            for item in &dest {
+                let parent_deleted = deleted.iter().any(|dir| item.relative.starts_with(&format!("{}/", dir)));
+                if parent_deleted || source_entries.contains_key(&item.relative[..]) {
+                    continue;
+                }
+                actions.push(Action::Delete { path: self.dest_path(&item.relative), dir: item.entry.is_dir() });
+                if item.entry.is_dir() {
+                    deleted.insert(&item.relative);
+                }
+            }
+        }
+
+        Ok(Plan { direction: self.direction, actions: actions })
+    }
+
+    /// The entries of the remote tree, None when it isn't a directory
+    fn remote_entries(&self, ftp: &mut FtpStream) -> Result<Option<Vec<WalkEntry>>> {
+        if !try!(ftp.is_dir(&self.remote)) {
+            return Ok(None);
+        }
+        let entries = try!(ftp.walk(&self.remote).collect::<Result<Vec<WalkEntry>>>());
+        // names leading out of the listed directories are dropped when listed, this keeps
+        // every local path, and so every deletion, under the local root whatever the walk gives
+        Ok(Some(entries.into_iter().filter(|item| local_path(&self.local, &item.relative).is_some()).collect()))
+    }
+
+    fn filtered(&self, entries: Vec<WalkEntry>) -> Vec<WalkEntry> {
+        entries.into_iter().filter(|item| self.filter.matches(&item.relative, item.entry.is_dir())).collect()
+    }
+
+    fn dest_path(&self, relative: &str) -> String {
+        let root = match self.direction {
+            Direction::Download => &self.local,
+            Direction::Upload => &self.remote,
+        };
+        if relative.is_empty() { root.clone() } else { join(root, relative) }
+    }
+
+    fn job(&self, relative: &str) -> Job {
+        let (remote, local) = (join(&self.remote, relative), join(&self.local, relative));
+        match self.direction {
+            Direction::Download => Job::get(&remote, &local),
+            Direction::Upload => Job::put(&local, &remote),
+        }
+    }
+
+    /// Tells whether the destination file `dest` differs from the source file `item`
+    fn compare(&self, ftp: &mut FtpStream, item: &WalkEntry, dest: &Entry) -> Result<Option<Change>> {
+        if item.entry.size != dest.size {
+            return Ok(Some(Change::Size));
+        }
+
+        match self.checksum {
+            Some(algorithm) => {
+                let remote = try!(ftp.remote_checksum(&join(&self.remote, &item.relative), algorithm));
+                let local = try!(file_checksum(join(&self.local, &item.relative), algorithm));
+                Ok(if remote.to_lowercase() == local { None } else { Some(Change::Checksum) })
+            }
+            None => Ok(if same_time(&item.entry.modified, &dest.modified) { None } else { Some(Change::Modified) }),
+        }
+    }
+}
+
+/// Compares modification times to the second, or to the minute when one of them has no
+/// seconds like the times listed by LIST, or to the day when one of them is a midnight like
+/// the dates LIST gives for files older than six months
+pub(crate) fn same_time(a: &Option<DateTime<UTC>>, b: &Option<DateTime<UTC>>) -> bool {
+    match (*a, *b) {
+        (Some(a), Some(b)) => {
+            let (a, b) = (a.timestamp(), b.timestamp());
+            if a % 86400 == 0 || b % 86400 == 0 {
+                a.div_euclid(86400) == b.div_euclid(86400)
+            } else if a % 60 == 0 || b % 60 == 0 {
+                a / 60 == b / 60
+            } else {
+                a == b
+            }
+        }
+        // without times the sizes alone decide
+        _ => true,
+    }
+}
+
+/// The steps making a destination tree identical to its source, returned by `Mirror::plan`
+#[derive(Debug, Clone)]
+pub struct Plan {
+    pub direction: Direction,
+    pub actions: Vec<Action>,
+}
+
+/// What went wrong while executing a `Plan`
+#[derive(Debug)]
+pub struct Outcome {
+    /// The results of the transfers, ordered as in the plan
+    pub results: Vec<JobResult>,
+    /// The directories and deletions which failed
+    pub errors: Vec<(Action, FtpError)>,
+}
+
+impl Plan {
+    /// The transfers of the plan
+    pub fn jobs(&self) -> Vec<Job> {
+        self.actions.iter().filter_map(|action| match *action {
+            Action::Transfer { ref job, .. } => Some(job.clone()),
+            _ => None,
+        }).collect()
+    }
+
+    /// Runs the plan: deletions first, then directories, then the transfers through `engine`
+    ///
+    /// Failed steps are reported in the outcome and don't stop the rest of the plan.
+    pub fn execute(&self, ftp: &mut FtpStream, engine: &TransferEngine) -> Outcome {
+        let mut errors = Vec::new();
+        let deletes = self.actions.iter().filter(|action| match **action {
+            Action::Delete { .. } => true,
+            _ => false,
+        });
+        let dirs = self.actions.iter().filter(|action| match **action {
+            Action::MakeDir(_) => true,
+            _ => false,
+        });
+
+        for action in deletes.chain(dirs) {
+            if let Err(err) = self.apply(ftp, action) {
+                errors.push((action.clone(), err));
+            }
+        }
+
+        Outcome {
+            results: engine.run(self.jobs()),
+            errors: errors,
+        }
+    }
+
+    fn apply(&self, ftp: &mut FtpStream, action: &Action) -> Result<()> {
+        match (self.direction, action) {
+            (Direction::Download, &Action::MakeDir(ref path)) => fs::create_dir_all(path).map_err(FtpError::from),
+            (Direction::Upload, &Action::MakeDir(ref path)) => ftp.mkdir(path).or_else(|_| ftp.mkdir_all(path)),
+            (Direction::Download, &Action::Delete { ref path, dir: true }) => {
+                fs::remove_dir_all(path).map_err(FtpError::from)
+            }
+            (Direction::Download, &Action::Delete { ref path, dir: false }) => {
+                fs::remove_file(path).map_err(FtpError::from)
+            }
+            (Direction::Upload, &Action::Delete { ref path, dir: true }) => ftp.rmdir_all(path),
+            (Direction::Upload, &Action::Delete { ref path, dir: false }) => ftp.rm(path),
+            (_, &Action::Transfer { .. }) => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,66 @@
+use std::env;
+use std::fs;
+use chrono::{UTC, TimeZone};
+use super::{Mirror, same_time};
+use super::super::listing::parse_list;
+use super::super::engine::TransferEngine;
+use super::super::mock::{MockServer, USER, PASSWORD};
+
+#[test]
+fn times() {
+    let time = |h, m, s| Some(UTC.ymd(2020, 1, 2).and_hms(h, m, s));
+    assert!(same_time(&time(3, 4, 5), &time(3, 4, 5)));
+    assert!(!same_time(&time(3, 4, 5), &time(3, 4, 6)));
+    // LIST times have no seconds
+    assert!(same_time(&time(3, 4, 0), &time(3, 4, 59)));
+    assert!(!same_time(&time(3, 4, 0), &time(3, 5, 1)));
+    assert!(same_time(&None, &time(3, 4, 5)));
+
+    // LIST only gives the date of older files
+    let listed = parse_list("-rw-r--r-- 1 ftp ftp 1 Jan  2  2020 old.txt").unwrap().modified;
+    assert!(same_time(&listed, &time(3, 4, 5)));
+    assert!(same_time(&time(23, 59, 59), &listed));
+    assert!(!same_time(&listed, &Some(UTC.ymd(2020, 1, 3).and_hms(3, 4, 5))));
+}
+
+#[test]
+fn mirror() {
+    let dir = env::temp_dir().join(format!("zftp-mirror-{}", ::std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("extra.txt"), b"extra").unwrap();
+    let local = dir.to_str().unwrap();
+    let server = MockServer::start();
+    server.fs().add_file("site/index.html", b"<html>").add_file("site/css/main.css", b"body {}");
+    let addr = server.addr();
+    let engine = TransferEngine::new(move || {
+        let mut ftp = try!(::FtpStream::connect_quiet(addr));
+        try!(ftp.login(USER, PASSWORD));
+        Ok(ftp)
+    });
+    let mut ftp = server.login();
+
+    // planning changes nothing
+    let plan = Mirror::download("site", local).delete(true).plan(&mut ftp).unwrap();
+    let actions: Vec<String> = plan.actions.iter().map(|action| action.to_string().replace(local, "$DIR")).collect();
+    assert_eq!(actions, vec!["mkdir   $DIR/css", "get     site/css/main.css -> $DIR/css/main.css (new)",
+                             "get     site/index.html -> $DIR/index.html (new)", "delete  $DIR/extra.txt"]);
+    assert!(dir.join("extra.txt").exists());
+    assert!(!server.received().iter().any(|command| command.starts_with("RETR")));
+
+    assert!(plan.execute(&mut ftp, &engine).errors.is_empty());
+    assert_eq!(fs::read(dir.join("css/main.css")).unwrap(), b"body {}");
+    assert!(!dir.join("extra.txt").exists());
+    assert!(Mirror::download("site", local).plan(&mut ftp).unwrap().actions.is_empty());
+
+    // and back, the remote file missing locally is only deleted when asked
+    fs::remove_file(dir.join("index.html")).unwrap();
+    fs::write(dir.join("css/main.css"), b"body { margin: 0 }").unwrap();
+    let plan = Mirror::upload(local, "site").plan(&mut ftp).unwrap();
+    assert_eq!(plan.actions.len(), 1);
+    plan.execute(&mut ftp, &engine);
+    assert_eq!(server.fs().contents("site/css/main.css").unwrap(), b"body { margin: 0 }");
+    Mirror::upload(local, "site").delete(true).plan(&mut ftp).unwrap().execute(&mut ftp, &engine);
+    assert!(!server.fs().exists("site/index.html"));
+    fs::remove_dir_all(&dir).unwrap();
+}
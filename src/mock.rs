@@ -0,0 +1,388 @@
+use std::collections::BTreeMap;
+use std::io as stdio;
+use std::io::{Read, Write, Cursor};
+use std::net::{TcpListener, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use chrono::{DateTime, UTC};
+use super::ftp::FtpStream;
+use super::listing::{Entry, EntryType};
+use super::server::{Filesystem, Hook, Intercept, Server};
+use super::walk::normalize;
+
+/// The user and password accepted by `MockServer`, besides anonymous logins
+pub const USER: &'static str = "user";
+pub const PASSWORD: &'static str = "secret";
+
+/// A file or a directory of a `MemoryFs`
+#[derive(Debug, Clone)]
+struct Node {
+    /// None for directories
+    data: Option<Vec<u8>>,
+    modified: DateTime<UTC>,
+    mode: u32,
+}
+
+impl Node {
+    fn dir() -> Node {
+        Node { data: None, modified: UTC::now(), mode: 0o755 }
+    }
+
+    fn file(data: Vec<u8>) -> Node {
+        Node { data: Some(data), modified: UTC::now(), mode: 0o644 }
+    }
+}
+
+/// An in-memory `Filesystem`, clones share the same files
+///
+/// The helpers used to set up and inspect the files take paths relative to the root,
+/// with or without a leading `/`.
+#[derive(Debug, Clone)]
+pub struct MemoryFs {
+    nodes: Arc<Mutex<BTreeMap<String, Node>>>,
+}
+
+impl MemoryFs {
+    /// An empty tree, holding only the root directory
+    pub fn new() -> MemoryFs {
+        let mut nodes = BTreeMap::new();
+        nodes.insert(String::from("/"), Node::dir());
+        MemoryFs { nodes: Arc::new(Mutex::new(nodes)) }
+    }
+
+    /// Adds a directory along with its missing parents
+    pub fn add_dir(&self, path: &str) -> &MemoryFs {
+        let path = normalize(path);
+        let mut nodes = self.nodes.lock().unwrap();
+        let mut prefix = String::new();
+        for part in path.split('/').filter(|part| part.len() > 0) {
+            prefix = format!("{}/{}", prefix, part);
+            nodes.entry(prefix.clone()).or_insert_with(Node::dir);
+        }
+        self
+    }
+
+    /// Adds or replaces a file, creating its missing parents
+    pub fn add_file(&self, path: &str, contents: &[u8]) -> &MemoryFs {
+        let path = normalize(path);
+        self.add_dir(parent(&path));
+        self.nodes.lock().unwrap().insert(path, Node::file(contents.to_vec()));
+        self
+    }
+
+    /// The contents of a file, None if there is no such file
+    pub fn contents(&self, path: &str) -> Option<Vec<u8>> {
+        self.nodes.lock().unwrap().get(&normalize(path)).and_then(|node| node.data.clone())
+    }
+
+    pub fn exists(&self, path: &str) -> bool {
+        self.nodes.lock().unwrap().contains_key(&normalize(path))
+    }
+
+    pub fn is_dir(&self, path: &str) -> bool {
+        self.nodes.lock().unwrap().get(&normalize(path)).map_or(false, |node| node.data.is_none())
+    }
+
+    pub fn modified(&self, path: &str) -> Option<DateTime<UTC>> {
+        self.nodes.lock().unwrap().get(&normalize(path)).map(|node| node.modified)
+    }
+
+    pub fn mode(&self, path: &str) -> Option<u32> {
+        self.nodes.lock().unwrap().get(&normalize(path)).map(|node| node.mode)
+    }
+
+    fn entry(path: &str, node: &Node) -> Entry {
+        let name = path.rsplit('/').next().unwrap_or("").to_string();
+        let mut entry = match node.data {
+            Some(ref data) => {
+                let mut entry = Entry::new(name, EntryType::File);
+                entry.size = Some(data.len() as u64);
+                entry
+            }
+            None => Entry::new(name, EntryType::Dir),
+        };
+        entry.modified = Some(node.modified);
+        entry.mode = Some(node.mode);
+        entry
+    }
+
+    /// Runs `f` on the node at `path`, failing if there is none
+    fn with_node<T, F>(&self, path: &str, f: F) -> stdio::Result<T>
+        where F: FnOnce(&mut Node) -> stdio::Result<T> {
+        match self.nodes.lock().unwrap().get_mut(path) {
+            Some(node) => f(node),
+            None => Err(not_found(path)),
+        }
+    }
+
+    /// Fails unless the parent of `path` is a directory
+    fn check_parent(&self, path: &str) -> stdio::Result<()> {
+        if self.is_dir(parent(path)) {
+            Ok(())
+        } else {
+            Err(not_found(parent(path)))
+        }
+    }
+}
+
+impl Default for MemoryFs {
+    fn default() -> MemoryFs {
+        MemoryFs::new()
+    }
+}
+
+impl Filesystem for MemoryFs {
+    fn metadata(&self, path: &str) -> stdio::Result<Entry> {
+        self.with_node(path, |node| Ok(MemoryFs::entry(path, node)))
+    }
+
+    fn read_dir(&self, path: &str) -> stdio::Result<Vec<Entry>> {
+        if !self.is_dir(path) {
+            return Err(not_found(path));
+        }
+        let nodes = self.nodes.lock().unwrap();
+        Ok(nodes.iter()
+           .filter(|&(child, _)| child != "/" && parent(child) == path)
+           .map(|(child, node)| MemoryFs::entry(child, node))
+           .collect())
+    }
+
+    fn open(&self, path: &str) -> stdio::Result<Box<dyn Read + Send>> {
+        self.with_node(path, |node| match node.data {
+            Some(ref data) => Ok(Box::new(Cursor::new(data.clone())) as Box<dyn Read + Send>),
+            None => Err(stdio::Error::new(stdio::ErrorKind::Other, "Is a directory")),
+        })
+    }
+
+    fn create(&self, path: &str, append: bool) -> stdio::Result<Box<dyn Write + Send>> {
+        try!(self.check_parent(path));
+        let data = match self.nodes.lock().unwrap().get(path) {
+            Some(&Node { data: None, .. }) => return Err(stdio::Error::new(stdio::ErrorKind::Other, "Is a directory")),
+            Some(&Node { data: Some(ref data), .. }) if append => data.clone(),
+            _ => Vec::new(),
+        };
+        // the file exists as soon as it is opened, like on disk
+        self.nodes.lock().unwrap().insert(path.to_string(), Node::file(data.clone()));
+        Ok(Box::new(MemoryFile { fs: self.clone(), path: path.to_string(), data: data }))
+    }
+
+    fn create_dir(&self, path: &str) -> stdio::Result<()> {
+        try!(self.check_parent(path));
+        let mut nodes = self.nodes.lock().unwrap();
+        if nodes.contains_key(path) {
+            return Err(stdio::Error::new(stdio::ErrorKind::AlreadyExists, format!("{}: File exists", path)));
+        }
+        nodes.insert(path.to_string(), Node::dir());
+        Ok(())
+    }
+
+    fn remove_dir(&self, path: &str) -> stdio::Result<()> {
+        if !self.is_dir(path) || path == "/" {
+            return Err(not_found(path));
+        }
+        if try!(self.read_dir(path)).len() > 0 {
+            return Err(stdio::Error::new(stdio::ErrorKind::Other, format!("{}: Directory not empty", path)));
+        }
+        self.nodes.lock().unwrap().remove(path);
+        Ok(())
+    }
+
+    fn remove_file(&self, path: &str) -> stdio::Result<()> {
+        if self.contents(path).is_none() {
+            return Err(not_found(path));
+        }
+        self.nodes.lock().unwrap().remove(path);
+        Ok(())
+    }
+
+    fn rename(&self, from: &str, to: &str) -> stdio::Result<()> {
+        try!(self.check_parent(to));
+        let mut nodes = self.nodes.lock().unwrap();
+        if !nodes.contains_key(from) || from == "/" {
+            return Err(not_found(from));
+        }
+        // a directory takes everything under it along
+        let inner = format!("{}/", from);
+        let moved: Vec<String> = nodes.keys().filter(|path| *path == from || path.starts_with(&inner)).cloned().collect();
+        for path in moved {
+            let node = nodes.remove(&path).unwrap();
+            nodes.insert(format!("{}{}", to, &path[from.len()..]), node);
+        }
+        Ok(())
+    }
+
+    fn set_modified(&self, path: &str, time: &DateTime<UTC>) -> stdio::Result<()> {
+        self.with_node(path, |node| {
+            node.modified = *time;
+            Ok(())
+        })
+    }
+
+    fn set_mode(&self, path: &str, mode: u32) -> stdio::Result<()> {
+        self.with_node(path, |node| {
+            node.mode = mode;
+            Ok(())
+        })
+    }
+}
+
+/// A file of a `MemoryFs` being written, its contents are stored on every flush
+struct MemoryFile {
+    fs: MemoryFs,
+    path: String,
+    data: Vec<u8>,
+}
+
+impl Write for MemoryFile {
+    fn write(&mut self, buf: &[u8]) -> stdio::Result<usize> {
+        self.data.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> stdio::Result<()> {
+        let path = self.path.clone();
+        let data = self.data.clone();
+        self.fs.with_node(&path, |node| {
+            node.data = Some(data);
+            node.modified = UTC::now();
+            Ok(())
+        })
+    }
+}
+
+impl Drop for MemoryFile {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+/// A scripted change to how the mock server handles a command
+#[derive(Debug, Clone)]
+enum Action {
+    Reply(String),
+    Delay(Duration),
+    Close,
+}
+
+/// The scripted actions and the commands received, shared by the sessions of a `MockServer`
+#[derive(Debug, Default)]
+struct Script {
+    actions: Mutex<Vec<(String, Action)>>,
+    received: Mutex<Vec<String>>,
+}
+
+impl Hook for Arc<Script> {
+    fn command(&self, verb: &str, arg: &str) -> Intercept {
+        let line = if arg.is_empty() { verb.to_string() } else { format!("{} {}", verb, arg) };
+        self.received.lock().unwrap().push(line);
+        loop {
+            let action = {
+                let mut actions = self.actions.lock().unwrap();
+                match actions.iter().position(|&(ref scripted, _)| scripted == verb) {
+                    Some(i) => actions.remove(i).1,
+                    None => return Intercept::Pass,
+                }
+            };
+            match action {
+                // a delay may come before another action for the same command
+                Action::Delay(delay) => thread::sleep(delay),
+                Action::Reply(reply) => return Intercept::Reply(reply),
+                Action::Close => return Intercept::Close,
+            }
+        }
+    }
+}
+
+/// A scriptable FTP server on a free localhost port, serving a `MemoryFs`
+///
+/// It accepts the `USER`/`PASSWORD` account and anonymous logins. Each scripted action
+/// applies once, to the next matching command of any session, in the order they were
+/// added. The server runs until the process exits.
+pub struct MockServer {
+    addr: SocketAddr,
+    fs: MemoryFs,
+    script: Arc<Script>,
+}
+
+impl MockServer {
+    /// Starts a server with an empty file system
+    pub fn start() -> MockServer {
+        MockServer::with_fs(MemoryFs::new())
+    }
+
+    /// Starts a server serving `fs`
+    pub fn with_fs(fs: MemoryFs) -> MockServer {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("mock server could not bind");
+        let addr = listener.local_addr().expect("mock server has no address");
+        let script = Arc::new(Script::default());
+        Server::new(fs.clone())
+            .account(USER, PASSWORD)
+            .anonymous(true)
+            .hook(script.clone())
+            .spawn(listener);
+
+        MockServer {
+            addr: addr,
+            fs: fs,
+            script: script,
+        }
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// The files served, changes are seen by the server right away
+    pub fn fs(&self) -> &MemoryFs {
+        &self.fs
+    }
+
+    /// Connects a quiet `FtpStream`, see `FtpStream::connect_quiet`
+    pub fn connect(&self) -> FtpStream {
+        FtpStream::connect_quiet(self.addr).expect("could not connect to the mock server")
+    }
+
+    /// Connects a quiet `FtpStream` logged in as `USER`
+    pub fn login(&self) -> FtpStream {
+        let mut ftp = self.connect();
+        ftp.login(USER, PASSWORD).expect("could not log into the mock server");
+        ftp
+    }
+
+    /// Answers the next `verb` command with `reply`, e.g. `550 Injected failure`, without handling it
+    pub fn reply(&self, verb: &str, reply: &str) -> &MockServer {
+        self.script(verb, Action::Reply(reply.to_string()))
+    }
+
+    /// Waits for `delay` before handling the next `verb` command
+    pub fn delay(&self, verb: &str, delay: Duration) -> &MockServer {
+        self.script(verb, Action::Delay(delay))
+    }
+
+    /// Closes the control connection instead of handling the next `verb` command
+    pub fn disconnect(&self, verb: &str) -> &MockServer {
+        self.script(verb, Action::Close)
+    }
+
+    /// The commands received so far by every session, e.g. `RETR notes.txt`
+    pub fn received(&self) -> Vec<String> {
+        self.script.received.lock().unwrap().clone()
+    }
+
+    fn script(&self, verb: &str, action: Action) -> &MockServer {
+        self.script.actions.lock().unwrap().push((verb.to_uppercase(), action));
+        self
+    }
+}
+
+fn parent(path: &str) -> &str {
+    match path.rfind('/') {
+        Some(0) | None => "/",
+        Some(slash) => &path[..slash],
+    }
+}
+
+fn not_found(path: &str) -> stdio::Error {
+    stdio::Error::new(stdio::ErrorKind::NotFound, format!("{}: No such file or directory", path))
+}
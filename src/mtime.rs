@@ -0,0 +1,39 @@
+use std::fs::File;
+use std::io as stdio;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use chrono::{DateTime, UTC, TimeZone};
+
+/// Converts a local file time to the UTC time used by MDTM and MFMT, truncated to the second
+pub fn to_utc(time: SystemTime) -> DateTime<UTC> {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(since) => UTC.timestamp(since.as_secs() as i64, 0),
+        Err(before) => UTC.timestamp(-(before.duration().as_secs() as i64), 0),
+    }
+}
+
+/// Converts a time read with MDTM to a local file time
+pub fn to_system_time(time: &DateTime<UTC>) -> SystemTime {
+    let secs = time.timestamp();
+    if secs >= 0 {
+        UNIX_EPOCH + Duration::from_secs(secs as u64)
+    } else {
+        UNIX_EPOCH - Duration::from_secs((-secs) as u64)
+    }
+}
+
+/// The modification time of a local file
+pub fn local_mtime<P: AsRef<Path>>(path: P) -> stdio::Result<DateTime<UTC>> {
+    path.as_ref().metadata()
+        .and_then(|meta| meta.modified())
+        .map(to_utc)
+}
+
+/// Sets the modification time of a local file
+pub fn set_local_mtime<P: AsRef<Path>>(path: P, time: &DateTime<UTC>) -> stdio::Result<()> {
+    File::options().write(true).open(path)
+        .and_then(|file| file.set_modified(to_system_time(time)))
+}
+
+#[cfg(test)]
+mod tests;
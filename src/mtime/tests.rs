@@ -0,0 +1,25 @@
+use std::env;
+use std::fs;
+use std::time::{Duration, UNIX_EPOCH};
+use chrono::{UTC, TimeZone};
+use super::{to_utc, to_system_time, local_mtime, set_local_mtime};
+
+#[test]
+fn conversions() {
+    let time = UTC.ymd(2020, 1, 2).and_hms(3, 4, 5);
+    assert_eq!(to_utc(to_system_time(&time)), time);
+    assert_eq!(to_utc(UNIX_EPOCH + Duration::from_millis(1999)), UTC.timestamp(1, 0));
+    let before = UTC.ymd(1969, 7, 20).and_hms(20, 17, 0);
+    assert_eq!(to_utc(to_system_time(&before)), before);
+}
+
+#[test]
+fn local_files() {
+    let path = env::temp_dir().join(format!("zftp-mtime-{}", ::std::process::id()));
+    fs::write(&path, b"data").unwrap();
+    let time = UTC.ymd(2001, 2, 3).and_hms(4, 5, 6);
+    set_local_mtime(&path, &time).unwrap();
+    assert_eq!(local_mtime(&path).unwrap(), time);
+    fs::remove_file(&path).unwrap();
+    assert!(set_local_mtime(&path, &time).is_err());
+}
@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs::File;
+use std::io as stdio;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// The credentials and macros of a `machine` or `default` entry
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Machine {
+    pub login: Option<String>,
+    pub password: Option<String>,
+    pub account: Option<String>,
+    /// The `macdef` macros defined after the entry, one command per line
+    pub macros: HashMap<String, Vec<String>>,
+}
+
+/// The entries of a `.netrc` file, see `Netrc::load`
+///
+/// Tokens are separated by white space and may be quoted with `"`, `#` starts a comment. A
+/// `macdef` takes the rest of its line as name and the following lines up to an empty one as
+/// body. Unknown keywords are skipped along with their value.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Netrc {
+    /// The `machine` entries in the order of the file
+    pub machines: Vec<(String, Machine)>,
+    pub default: Option<Machine>,
+}
+
+impl Netrc {
+    /// The file named by `$NETRC`, or `.netrc` in the home directory
+    pub fn default_path() -> Option<PathBuf> {
+        env::var_os("NETRC").map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".netrc")))
+    }
+
+    /// Reads and parses a netrc file, a malformed file yields an `InvalidData` error
+    pub fn load<P: AsRef<Path>>(path: P) -> stdio::Result<Netrc> {
+        let mut text = String::new();
+        try!(File::open(path).and_then(|mut file| file.read_to_string(&mut text)));
+        text.parse().map_err(|err| stdio::Error::new(stdio::ErrorKind::InvalidData, err))
+    }
+
+    /// The entry of `host`, or the `default` entry if there is none
+    pub fn find(&self, host: &str) -> Option<&Machine> {
+        self.machines.iter()
+            .find(|&&(ref name, _)| name.eq_ignore_ascii_case(host))
+            .map(|&(_, ref machine)| machine)
+            .or(self.default.as_ref())
+    }
+}
+
+impl FromStr for Netrc {
+    type Err = String;
+
+    fn from_str(s: &str) -> ::std::result::Result<Netrc, String> {
+        let mut netrc = Netrc::default();
+        let mut tokens = Tokens { text: s, pos: 0 };
+        // whether the following keywords belong to the default entry or to the last machine
+        let mut in_default = false;
+
+        while let Some(token) = tokens.next() {
+            match &token[..] {
+                "machine" => {
+                    let name = try!(tokens.value("machine"));
+                    netrc.machines.push((name, Machine::default()));
+                    in_default = false;
+                }
+                "default" => {
+                    netrc.default = Some(Machine::default());
+                    in_default = true;
+                }
+                keyword => {
+                    let entry = if in_default {
+                        netrc.default.as_mut()
+                    } else {
+                        netrc.machines.last_mut().map(|&mut (_, ref mut machine)| machine)
+                    };
+                    let entry = match entry {
+                        Some(entry) => entry,
+                        None => return Err(format!("{} before any machine or default entry", keyword)),
+                    };
+                    match keyword {
+                        "login" => entry.login = Some(try!(tokens.value(keyword))),
+                        "password" | "passwd" => entry.password = Some(try!(tokens.value(keyword))),
+                        "account" => entry.account = Some(try!(tokens.value(keyword))),
+                        "macdef" => {
+                            let name = tokens.rest_of_line().trim().to_string();
+                            if name.is_empty() {
+                                return Err(String::from("macdef without a name"));
+                            }
+                            let body = tokens.macro_body();
+                            entry.macros.insert(name, body);
+                        }
+                        _ => {
+                            try!(tokens.value(keyword));
+                        }
+                    }
+                }
+            }
+        }
+        Ok(netrc)
+    }
+}
+
+/// Splits the text of a netrc file into tokens
+struct Tokens<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> Tokens<'a> {
+    fn skip_spaces(&mut self) {
+        let rest = &self.text[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    /// The token following `keyword`, which must have one
+    fn value(&mut self, keyword: &str) -> ::std::result::Result<String, String> {
+        self.next().ok_or(format!("{} without a value", keyword))
+    }
+
+    fn rest_of_line(&mut self) -> &'a str {
+        let rest = &self.text[self.pos..];
+        let end = rest.find('\n').map_or(rest.len(), |newline| newline + 1);
+        self.pos += end;
+        &rest[..end]
+    }
+
+    /// The lines up to the next empty one, which ends the macro
+    fn macro_body(&mut self) -> Vec<String> {
+        let mut lines = Vec::new();
+        while self.pos < self.text.len() {
+            let line = self.rest_of_line().trim_end_matches(|c| c == '\r' || c == '\n');
+            if line.is_empty() {
+                break;
+            }
+            lines.push(line.to_string());
+        }
+        lines
+    }
+}
+
+impl<'a> Iterator for Tokens<'a> {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        self.skip_spaces();
+        // comments run to the end of the line
+        while self.text[self.pos..].starts_with('#') {
+            self.rest_of_line();
+            self.skip_spaces();
+        }
+        let rest = &self.text[self.pos..];
+        if rest.is_empty() {
+            return None;
+        }
+
+        let mut token = String::new();
+        let mut chars = rest.char_indices();
+        let quoted = rest.starts_with('"');
+        if quoted {
+            chars.next();
+        }
+        let mut end = rest.len();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' if quoted => {
+                    end = i + 1;
+                    break;
+                }
+                '\\' => {
+                    if let Some((_, escaped)) = chars.next() {
+                        token.push(escaped);
+                    }
+                }
+                _ if c.is_whitespace() && !quoted => {
+                    end = i;
+                    break;
+                }
+                _ => token.push(c),
+            }
+        }
+        self.pos += end;
+        Some(token)
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,45 @@
+use super::Netrc;
+
+const NETRC: &'static str = "# deploy targets
+machine ftp.example.com login deploy password \"s3cret pass\"
+    account ops
+macdef init
+cd /incoming
+prompt
+
+machine mirror.example.org
+login anonymous password ops@example.com
+default login guest password guest
+";
+
+#[test]
+fn parse() {
+    let netrc: Netrc = NETRC.parse().unwrap();
+    assert_eq!(netrc.machines.len(), 2);
+
+    let deploy = netrc.find("FTP.example.com").unwrap();
+    assert_eq!(deploy.login, Some(String::from("deploy")));
+    assert_eq!(deploy.password, Some(String::from("s3cret pass")));
+    assert_eq!(deploy.account, Some(String::from("ops")));
+    assert_eq!(deploy.macros["init"], vec!["cd /incoming", "prompt"]);
+
+    let mirror = netrc.find("mirror.example.org").unwrap();
+    assert_eq!(mirror.login, Some(String::from("anonymous")));
+    assert!(mirror.macros.is_empty());
+
+    let other = netrc.find("other.example.net").unwrap();
+    assert_eq!(other.login, Some(String::from("guest")));
+}
+
+#[test]
+fn malformed() {
+    assert!("login orphan".parse::<Netrc>().is_err());
+    assert!("machine".parse::<Netrc>().is_err());
+    assert!("machine host password".parse::<Netrc>().is_err());
+    assert!("machine host\nmacdef\n".parse::<Netrc>().is_err());
+    // unknown keywords are skipped with their value
+    let netrc: Netrc = "machine host port 2121 login user".parse().unwrap();
+    assert_eq!(netrc.find("host").unwrap().login, Some(String::from("user")));
+    assert!(netrc.find("other").is_none());
+    assert_eq!("".parse::<Netrc>().unwrap(), Netrc::default());
+}
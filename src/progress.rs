@@ -0,0 +1,62 @@
+use std::time::Duration;
+
+/// Which way the data of a transfer flows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Download,
+    Upload,
+}
+
+/// The state of a running transfer, passed to a `ProgressObserver`
+#[derive(Debug, Clone, Copy)]
+pub struct Progress {
+    pub direction: Direction,
+    /// Bytes transferred so far
+    pub bytes: u64,
+    /// Expected size of the transfer, if the server or the caller told us
+    pub total: Option<u64>,
+    /// Time since the data connection was opened
+    pub elapsed: Duration,
+}
+
+impl Progress {
+    /// Average transfer rate in bytes per second
+    pub fn rate(&self) -> f64 {
+        let secs = self.secs();
+        if secs > 0.0 { self.bytes as f64 / secs } else { 0.0 }
+    }
+
+    /// Elapsed time in seconds
+    pub fn secs(&self) -> f64 {
+        self.elapsed.as_secs() as f64 + self.elapsed.subsec_nanos() as f64 / 1e9
+    }
+
+    /// Completed fraction of the transfer between 0 and 1, if the total is known
+    pub fn fraction(&self) -> Option<f64> {
+        match self.total {
+            Some(0) => Some(1.0),
+            Some(total) => Some((self.bytes as f64 / total as f64).min(1.0)),
+            None => None,
+        }
+    }
+}
+
+/// Receives updates from the transfers of a `FtpStream`, see `FtpStream::set_progress`
+pub trait ProgressObserver {
+    /// Called each time data went through the data connection
+    fn update(&mut self, progress: &Progress);
+
+    /// Called once the data connection is closed, before the server's final reply is read
+    fn finish(&mut self, progress: &Progress) {
+        self.update(progress);
+    }
+}
+
+impl<F: FnMut(&Progress)> ProgressObserver for F {
+    fn update(&mut self, progress: &Progress) {
+        self(progress)
+    }
+}
+
+#[cfg(test)]
+mod tests;
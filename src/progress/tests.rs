@@ -0,0 +1,21 @@
+use std::time::Duration;
+use super::{Progress, Direction};
+
+fn progress(bytes: u64, total: Option<u64>, millis: u64) -> Progress {
+    Progress { direction: Direction::Download, bytes: bytes, total: total, elapsed: Duration::from_millis(millis) }
+}
+
+#[test]
+fn rate() {
+    assert_eq!(progress(1000, None, 500).rate(), 2000.0);
+    assert_eq!(progress(1000, None, 0).rate(), 0.0);
+}
+
+#[test]
+fn fraction() {
+    assert_eq!(progress(25, Some(100), 1).fraction(), Some(0.25));
+    // servers may send more than the size they reported
+    assert_eq!(progress(150, Some(100), 1).fraction(), Some(1.0));
+    assert_eq!(progress(0, Some(0), 1).fraction(), Some(1.0));
+    assert_eq!(progress(25, None, 1).fraction(), None);
+}
@@ -0,0 +1,1035 @@
+use std::collections::HashMap;
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io as stdio;
+use std::io::{Read, Write, BufRead, BufReader, copy, sink};
+use std::net::{TcpListener, TcpStream, IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use chrono::{DateTime, UTC, TimeZone};
+use flate2::Compression;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use super::checksum::{Algorithm, Hasher};
+use super::listing::{Entry, EntryType};
+use super::mtime::{to_utc, set_local_mtime};
+use super::walk::{join, normalize};
+use super::status;
+
+/// How long a data command waits for the client to connect
+const DATA_TIMEOUT_SECS: u64 = 10;
+
+/// The files served by a `Server`
+///
+/// Paths are absolute and normalized, `/` being the root of the served tree. The entries
+/// returned by `metadata` and `read_dir` should tell their size, modification time and mode.
+pub trait Filesystem: Send + Sync {
+    fn metadata(&self, path: &str) -> stdio::Result<Entry>;
+
+    /// The entries of the directory `path`, sorted by name
+    fn read_dir(&self, path: &str) -> stdio::Result<Vec<Entry>>;
+
+    fn open(&self, path: &str) -> stdio::Result<Box<dyn Read + Send>>;
+
+    /// Opens the file `path` for writing, truncating it unless `append` is set
+    fn create(&self, path: &str, append: bool) -> stdio::Result<Box<dyn Write + Send>>;
+
+    fn create_dir(&self, path: &str) -> stdio::Result<()>;
+
+    fn remove_dir(&self, path: &str) -> stdio::Result<()>;
+
+    fn remove_file(&self, path: &str) -> stdio::Result<()>;
+
+    fn rename(&self, from: &str, to: &str) -> stdio::Result<()>;
+
+    fn set_modified(&self, path: &str, time: &DateTime<UTC>) -> stdio::Result<()>;
+
+    fn set_mode(&self, path: &str, mode: u32) -> stdio::Result<()>;
+}
+
+/// A local directory served as the root of the tree
+///
+/// The paths given by the server can't go above the root, but symbolic links inside it
+/// are followed wherever they lead.
+#[derive(Debug, Clone)]
+pub struct LocalFs {
+    root: PathBuf,
+}
+
+impl LocalFs {
+    pub fn new<P: Into<PathBuf>>(root: P) -> LocalFs {
+        LocalFs { root: root.into() }
+    }
+
+    fn local(&self, path: &str) -> PathBuf {
+        self.root.join(path.trim_start_matches('/'))
+    }
+
+    fn entry(name: String, path: &PathBuf) -> stdio::Result<Entry> {
+        let meta = try!(fs::symlink_metadata(path));
+        let entry_type = if meta.file_type().is_symlink() {
+            EntryType::Symlink
+        } else if meta.is_dir() {
+            EntryType::Dir
+        } else if meta.is_file() {
+            EntryType::File
+        } else {
+            EntryType::Other
+        };
+        let mut entry = Entry::new(name, entry_type);
+        entry.size = Some(meta.len());
+        entry.modified = meta.modified().ok().map(to_utc);
+        if entry_type == EntryType::Symlink {
+            entry.target = fs::read_link(path).ok().map(|target| target.to_string_lossy().into_owned());
+        }
+        unix_facts(&meta, &mut entry);
+        Ok(entry)
+    }
+}
+
+#[cfg(unix)]
+fn unix_facts(meta: &fs::Metadata, entry: &mut Entry) {
+    use std::os::unix::fs::MetadataExt;
+    entry.mode = Some(meta.mode() & 0o7777);
+    entry.unique = Some(format!("{:x}.{:x}", meta.dev(), meta.ino()));
+}
+
+#[cfg(not(unix))]
+fn unix_facts(_meta: &fs::Metadata, _entry: &mut Entry) {}
+
+impl Filesystem for LocalFs {
+    /// Symbolic links are followed, unlike in `read_dir` which lists them as links
+    fn metadata(&self, path: &str) -> stdio::Result<Entry> {
+        let local = self.local(path);
+        let name = path.rsplit('/').next().unwrap_or("").to_string();
+        let entry = try!(LocalFs::entry(name.clone(), &local));
+        if !entry.is_symlink() {
+            return Ok(entry);
+        }
+        // a link is used as what it leads to, e.g. CWD enters a linked directory
+        let meta = try!(fs::metadata(&local));
+        let mut followed = Entry::new(name, if meta.is_dir() { EntryType::Dir } else { EntryType::File });
+        followed.size = Some(meta.len());
+        followed.modified = meta.modified().ok().map(to_utc);
+        unix_facts(&meta, &mut followed);
+        Ok(followed)
+    }
+
+    fn read_dir(&self, path: &str) -> stdio::Result<Vec<Entry>> {
+        let mut entries = Vec::new();
+        for child in try!(fs::read_dir(self.local(path))) {
+            let child = try!(child);
+            let name = child.file_name().to_string_lossy().into_owned();
+            entries.push(try!(LocalFs::entry(name, &child.path())));
+        }
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(entries)
+    }
+
+    fn open(&self, path: &str) -> stdio::Result<Box<dyn Read + Send>> {
+        File::open(self.local(path)).map(|file| Box::new(file) as Box<dyn Read + Send>)
+    }
+
+    fn create(&self, path: &str, append: bool) -> stdio::Result<Box<dyn Write + Send>> {
+        let mut options = OpenOptions::new();
+        if append {
+            options.append(true);
+        } else {
+            options.write(true).truncate(true);
+        }
+        options.create(true).open(self.local(path)).map(|file| Box::new(file) as Box<dyn Write + Send>)
+    }
+
+    fn create_dir(&self, path: &str) -> stdio::Result<()> {
+        fs::create_dir(self.local(path))
+    }
+
+    fn remove_dir(&self, path: &str) -> stdio::Result<()> {
+        if path == "/" {
+            return Err(stdio::Error::new(stdio::ErrorKind::PermissionDenied, "Can't remove the root"));
+        }
+        fs::remove_dir(self.local(path))
+    }
+
+    fn remove_file(&self, path: &str) -> stdio::Result<()> {
+        fs::remove_file(self.local(path))
+    }
+
+    fn rename(&self, from: &str, to: &str) -> stdio::Result<()> {
+        fs::rename(self.local(from), self.local(to))
+    }
+
+    fn set_modified(&self, path: &str, time: &DateTime<UTC>) -> stdio::Result<()> {
+        set_local_mtime(self.local(path), time)
+    }
+
+    #[cfg(unix)]
+    fn set_mode(&self, path: &str, mode: u32) -> stdio::Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(self.local(path), fs::Permissions::from_mode(mode))
+    }
+
+    #[cfg(not(unix))]
+    fn set_mode(&self, _path: &str, _mode: u32) -> stdio::Result<()> {
+        Err(stdio::Error::new(stdio::ErrorKind::Other, "Permissions are not supported"))
+    }
+}
+
+/// What a `Hook` makes the server do with a command
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Intercept {
+    /// Handles the command as usual
+    Pass,
+    /// Sends this reply instead, e.g. `550 Injected failure`
+    Reply(String),
+    /// Closes the control connection without replying
+    Close,
+}
+
+/// Sees every command before the server handles it, used to script the mock server
+pub trait Hook: Send + Sync {
+    /// `verb` is uppercase, `arg` is the rest of the line
+    fn command(&self, verb: &str, arg: &str) -> Intercept;
+}
+
+/// A small FTP server serving a `Filesystem`, one thread per client
+///
+/// It implements the commands `FtpStream` sends, with passive (PASV, EPSV) and active (PORT)
+/// data connections and MODE Z compression.
+#[derive(Clone)]
+pub struct Server {
+    fs: Arc<dyn Filesystem>,
+    accounts: HashMap<String, String>,
+    anonymous: bool,
+    read_only: bool,
+    fxp: bool,
+    hook: Option<Arc<dyn Hook>>,
+}
+
+impl Server {
+    /// A server without accounts, see `account` and `anonymous`
+    pub fn new<F: Filesystem + 'static>(fs: F) -> Server {
+        Server {
+            fs: Arc::new(fs),
+            accounts: HashMap::new(),
+            anonymous: false,
+            read_only: false,
+            fxp: false,
+            hook: None,
+        }
+    }
+
+    /// Lets `user` log in with `password`
+    pub fn account(mut self, user: &str, password: &str) -> Server {
+        self.accounts.insert(user.to_string(), password.to_string());
+        self
+    }
+
+    /// Lets `anonymous` and `ftp` log in with any password, off by default
+    pub fn anonymous(mut self, anonymous: bool) -> Server {
+        self.anonymous = anonymous;
+        self
+    }
+
+    /// Refuses every command changing the files, off by default
+    pub fn read_only(mut self, read_only: bool) -> Server {
+        self.read_only = read_only;
+        self
+    }
+
+    /// Lets PORT name another host than the client's, for server-to-server transfers, off by
+    /// default
+    ///
+    /// Otherwise a client could make the server connect anywhere it can reach (FTP bounce).
+    pub fn fxp(mut self, fxp: bool) -> Server {
+        self.fxp = fxp;
+        self
+    }
+
+    pub fn hook<H: Hook + 'static>(mut self, hook: H) -> Server {
+        self.hook = Some(Arc::new(hook));
+        self
+    }
+
+    /// Accepts clients on `listener` until it fails
+    pub fn serve(self, listener: TcpListener) -> stdio::Result<()> {
+        for client in listener.incoming() {
+            let client = try!(client);
+            let server = self.clone();
+            thread::spawn(move || {
+                // a client going away isn't the server's problem
+                let _ = Session::new(server, client).and_then(|mut session| session.run());
+            });
+        }
+        Ok(())
+    }
+
+    /// Serves `listener` from a background thread
+    pub fn spawn(self, listener: TcpListener) {
+        thread::spawn(move || {
+            let _ = self.serve(listener);
+        });
+    }
+}
+
+/// The state of a client's control connection
+struct Session {
+    server: Server,
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    local_ip: IpAddr,
+    peer_ip: IpAddr,
+    user: Option<String>,
+    logged_in: bool,
+    cwd: String,
+    rename_from: Option<String>,
+    rest: u64,
+    compressed: bool,
+    passive: Option<TcpListener>,
+    /// Where to connect for the next transfer, set by PORT
+    active: Option<SocketAddr>,
+    hash: Algorithm,
+    umask: u32,
+    idle: u64,
+}
+
+/// What a command handler wants to happen next
+enum Next {
+    Continue,
+    Quit,
+}
+
+type Handled = stdio::Result<Next>;
+
+impl Session {
+    fn new(server: Server, client: TcpStream) -> stdio::Result<Session> {
+        let local_ip = try!(client.local_addr()).ip();
+        let peer_ip = try!(client.peer_addr()).ip();
+        let writer = try!(client.try_clone());
+        Ok(Session {
+            server: server,
+            reader: BufReader::new(client),
+            writer: writer,
+            local_ip: local_ip,
+            peer_ip: peer_ip,
+            user: None,
+            logged_in: false,
+            cwd: String::from("/"),
+            rename_from: None,
+            rest: 0,
+            compressed: false,
+            passive: None,
+            active: None,
+            hash: Algorithm::Sha256,
+            umask: 0o022,
+            idle: 900,
+        })
+    }
+
+    fn run(&mut self) -> stdio::Result<()> {
+        try!(self.reply(status::READY, "Service ready"));
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if try!(self.reader.read_line(&mut line)) == 0 {
+                return Ok(());
+            }
+            let line = line.trim_end_matches(|c| c == '\r' || c == '\n');
+            let (verb, arg) = match line.find(' ') {
+                Some(space) => (line[..space].to_uppercase(), line[space + 1..].to_string()),
+                None => (line.to_uppercase(), String::new()),
+            };
+
+            let intercepted = match self.server.hook {
+                Some(ref hook) => hook.command(&verb, &arg),
+                None => Intercept::Pass,
+            };
+            match intercepted {
+                Intercept::Pass => (),
+                Intercept::Reply(reply) => {
+                    try!(self.send(&format!("{}\r\n", reply.trim_end())));
+                    continue;
+                }
+                Intercept::Close => return Ok(()),
+            }
+
+            if let Next::Quit = try!(self.handle(&verb, &arg)) {
+                return Ok(());
+            }
+        }
+    }
+
+    fn send(&mut self, text: &str) -> stdio::Result<()> {
+        try!(self.writer.write_all(text.as_bytes()));
+        self.writer.flush()
+    }
+
+    fn reply(&mut self, code: u32, text: &str) -> Handled {
+        try!(self.send(&format!("{} {}\r\n", code, text)));
+        Ok(Next::Continue)
+    }
+
+    /// Sends a multiple lines reply, the inner lines are indented by a space
+    fn reply_lines(&mut self, code: u32, first: &str, lines: &[String], last: &str) -> Handled {
+        let mut text = format!("{}-{}\r\n", code, first);
+        for line in lines {
+            text.push_str(&format!(" {}\r\n", line));
+        }
+        text.push_str(&format!("{} {}\r\n", code, last));
+        try!(self.send(&text));
+        Ok(Next::Continue)
+    }
+
+    fn fail(&mut self, err: stdio::Error) -> Handled {
+        let text = format!("{}", err);
+        self.reply(status::FILE_UNAVAILABLE, &text)
+    }
+
+    /// The absolute path of a command argument
+    fn path(&self, arg: &str) -> String {
+        normalize(&join(&self.cwd, arg))
+    }
+
+    fn handle(&mut self, verb: &str, arg: &str) -> Handled {
+        match verb {
+            "USER" => return self.user(arg),
+            "PASS" => return self.pass(arg),
+            "QUIT" => {
+                try!(self.reply(status::CLOSING, "Goodbye"));
+                return Ok(Next::Quit);
+            }
+            "FEAT" => return self.feat(),
+            "SYST" => return self.reply(status::NAME, "UNIX Type: L8"),
+            "NOOP" => return self.reply(status::COMMAND_OK, "NOOP ok"),
+            _ if !self.logged_in => return self.reply(status::NOT_LOGGED_IN, "Please login with USER and PASS"),
+            _ => (),
+        }
+
+        let writes = ["STOR", "APPE", "STOU", "DELE", "MKD", "RMD", "RNFR", "RNTO", "MFMT"];
+        if self.server.read_only && writes.contains(&verb) {
+            return self.reply(status::FILE_UNAVAILABLE, "Permission denied");
+        }
+
+        match verb {
+            "PWD" => {
+                let text = format!("\"{}\" is the current directory", self.cwd);
+                self.reply(status::PATH_CREATED, &text)
+            }
+            "CWD" => self.cwd(arg),
+            "CDUP" => self.cwd(".."),
+            "TYPE" => match &arg.to_uppercase()[..] {
+                "A" | "A N" | "I" | "L 8" => self.reply(status::COMMAND_OK, &format!("Type set to {}", arg)),
+                _ => self.reply(status::NOT_IMPLEMENTED_PARAMETER, "Unsupported type"),
+            },
+            "MODE" => self.mode(arg),
+            "STRU" if arg.eq_ignore_ascii_case("F") => self.reply(status::COMMAND_OK, "Structure set to F"),
+            "OPTS" => self.opts(arg),
+            "PASV" => self.pasv(),
+            "EPSV" => self.epsv(arg),
+            "PORT" => match parse_port(arg) {
+                Some(addr) if addr.ip() != self.peer_ip && !self.server.fxp => {
+                    self.reply(status::BAD_COMMAND, "PORT must name the client's own address")
+                }
+                Some(addr) => {
+                    self.passive = None;
+                    self.active = Some(addr);
+                    self.reply(status::COMMAND_OK, "PORT command successful")
+                }
+                None => self.reply(status::BAD_ARGUMENTS, "Invalid PORT address"),
+            },
+            "REST" => match arg.parse() {
+                Ok(offset) => {
+                    self.rest = offset;
+                    self.reply(status::REQUEST_FILE_PENDING, &format!("Restarting at {}", offset))
+                }
+                Err(_) => self.reply(status::BAD_ARGUMENTS, "Invalid offset"),
+            },
+            "ALLO" => self.reply(status::COMMAND_NOT_IMPLEMENTED, "No storage allocation necessary"),
+            "ABOR" => self.reply(status::DATA_CONNECTION_OPEN, "No transfer to abort"),
+            "SIZE" => self.size(arg),
+            "MDTM" => self.mdtm(arg),
+            "MFMT" => self.mfmt(arg),
+            "DELE" => {
+                let path = self.path(arg);
+                match self.server.fs.remove_file(&path) {
+                    Ok(()) => self.reply(status::REQUESTED_FILE_ACTION_OK, "File removed"),
+                    Err(err) => self.fail(err),
+                }
+            }
+            "MKD" => {
+                let path = self.path(arg);
+                match self.server.fs.create_dir(&path) {
+                    Ok(()) => self.reply(status::PATH_CREATED, &format!("\"{}\" created", path)),
+                    Err(err) => self.fail(err),
+                }
+            }
+            "RMD" => {
+                let path = self.path(arg);
+                match self.server.fs.remove_dir(&path) {
+                    Ok(()) => self.reply(status::REQUESTED_FILE_ACTION_OK, "Directory removed"),
+                    Err(err) => self.fail(err),
+                }
+            }
+            "RNFR" => {
+                let path = self.path(arg);
+                match self.server.fs.metadata(&path) {
+                    Ok(_) => {
+                        self.rename_from = Some(path);
+                        self.reply(status::REQUEST_FILE_PENDING, "Ready for RNTO")
+                    }
+                    Err(err) => self.fail(err),
+                }
+            }
+            "RNTO" => match self.rename_from.take() {
+                Some(from) => {
+                    let to = self.path(arg);
+                    match self.server.fs.rename(&from, &to) {
+                        Ok(()) => self.reply(status::REQUESTED_FILE_ACTION_OK, "Rename successful"),
+                        Err(err) => self.fail(err),
+                    }
+                }
+                None => self.reply(status::BAD_SEQUENCE, "RNFR required first"),
+            },
+            "RETR" => self.retr(arg),
+            "STOR" => self.store(arg, false),
+            "APPE" => self.store(arg, true),
+            "STOU" => self.store_unique(arg),
+            "LIST" => self.list(arg, Listing::Long),
+            "NLST" => self.list(arg, Listing::Names),
+            "MLSD" => self.list(arg, Listing::Facts),
+            "MLST" => {
+                let path = self.path(arg);
+                match self.server.fs.metadata(&path) {
+                    Ok(entry) => {
+                        let facts = vec![format!("{}{}", facts(&entry), path)];
+                        self.reply_lines(status::REQUESTED_FILE_ACTION_OK, "Listing", &facts, "End")
+                    }
+                    Err(err) => self.fail(err),
+                }
+            }
+            "HASH" => {
+                let path = self.path(arg);
+                let algorithm = self.hash;
+                match self.checksum(&path, algorithm) {
+                    Ok((size, value)) => {
+                        let text = format!("{} 0-{} {} {}", algorithm.hash_name(), size, value, arg);
+                        self.reply(status::FILE, &text)
+                    }
+                    Err(err) => self.fail(err),
+                }
+            }
+            "XCRC" | "XMD5" | "XSHA1" | "XSHA256" => {
+                let algorithm = match verb {
+                    "XCRC" => Algorithm::Crc32,
+                    "XMD5" => Algorithm::Md5,
+                    "XSHA1" => Algorithm::Sha1,
+                    _ => Algorithm::Sha256,
+                };
+                let path = self.path(arg);
+                match self.checksum(&path, algorithm) {
+                    Ok((_, value)) => self.reply(status::REQUESTED_FILE_ACTION_OK, &value),
+                    Err(err) => self.fail(err),
+                }
+            }
+            "SITE" => self.site(arg),
+            _ => self.reply(status::NOT_IMPLEMENTED, &format!("{} not implemented", verb)),
+        }
+    }
+
+    fn user(&mut self, name: &str) -> Handled {
+        self.logged_in = false;
+        self.user = Some(name.to_string());
+        if self.server.anonymous && is_anonymous(name) {
+            self.reply(status::NEED_PASSWORD, "Anonymous login ok, send your email as password")
+        } else {
+            self.reply(status::NEED_PASSWORD, &format!("Password required for {}", name))
+        }
+    }
+
+    fn pass(&mut self, password: &str) -> Handled {
+        let accepted = match self.user {
+            Some(ref user) if self.server.anonymous && is_anonymous(user) => true,
+            Some(ref user) => self.server.accounts.get(user).map_or(false, |expected| expected == password),
+            None => return self.reply(status::BAD_SEQUENCE, "Login with USER first"),
+        };
+        if accepted {
+            self.logged_in = true;
+            self.reply(status::LOGGED_IN, "Login successful")
+        } else {
+            self.user = None;
+            self.reply(status::NOT_LOGGED_IN, "Login incorrect")
+        }
+    }
+
+    fn feat(&mut self) -> Handled {
+        let hashes: Vec<String> = [Algorithm::Sha256, Algorithm::Sha1, Algorithm::Md5, Algorithm::Crc32].iter()
+            .map(|algorithm| {
+                let selected = if *algorithm == self.hash { "*" } else { "" };
+                format!("{}{}", algorithm.hash_name(), selected)
+            })
+            .collect();
+        let mut features: Vec<String> = ["EPSV", "MDTM", "MFMT", "MLST type*;size*;modify*;unix.mode*;",
+                                         "MODE Z", "REST STREAM", "SIZE", "UTF8", "XCRC", "XMD5", "XSHA1",
+                                         "XSHA256"].iter().map(|feature| feature.to_string()).collect();
+        features.push(format!("HASH {}", hashes.join(";")));
+        features.sort();
+        self.reply_lines(status::SYSTEM, "Features:", &features, "End")
+    }
+
+    fn cwd(&mut self, arg: &str) -> Handled {
+        let path = self.path(arg);
+        match self.server.fs.metadata(&path) {
+            Ok(ref entry) if entry.is_dir() => {
+                self.cwd = path;
+                let text = format!("Directory changed to {}", self.cwd);
+                self.reply(status::REQUESTED_FILE_ACTION_OK, &text)
+            }
+            Ok(_) => self.reply(status::FILE_UNAVAILABLE, "Not a directory"),
+            Err(err) => self.fail(err),
+        }
+    }
+
+    fn mode(&mut self, arg: &str) -> Handled {
+        match &arg.to_uppercase()[..] {
+            "S" => self.compressed = false,
+            "Z" => self.compressed = true,
+            _ => return self.reply(status::NOT_IMPLEMENTED_PARAMETER, "Unsupported mode"),
+        }
+        self.reply(status::COMMAND_OK, &format!("Mode set to {}", arg.to_uppercase()))
+    }
+
+    fn opts(&mut self, arg: &str) -> Handled {
+        let mut words = arg.split_whitespace();
+        let option = words.next().unwrap_or("").to_uppercase();
+        match (&option[..], words.next()) {
+            ("HASH", None) => {
+                let name = self.hash.hash_name();
+                self.reply(status::COMMAND_OK, name)
+            }
+            ("HASH", Some(name)) => match name.parse::<Algorithm>() {
+                Ok(algorithm) => {
+                    self.hash = algorithm;
+                    self.reply(status::COMMAND_OK, algorithm.hash_name())
+                }
+                Err(_) => self.reply(status::BAD_ARGUMENTS, "Unknown algorithm"),
+            },
+            ("UTF8", _) => self.reply(status::COMMAND_OK, "Always in UTF8 mode"),
+            _ => self.reply(status::BAD_ARGUMENTS, "Option not understood"),
+        }
+    }
+
+    fn listen(&mut self) -> stdio::Result<u16> {
+        let listener = try!(TcpListener::bind(SocketAddr::new(self.local_ip, 0)));
+        let port = try!(listener.local_addr()).port();
+        self.passive = Some(listener);
+        self.active = None;
+        Ok(port)
+    }
+
+    fn pasv(&mut self) -> Handled {
+        let octets = match self.local_ip {
+            IpAddr::V4(ip) => ip.octets(),
+            IpAddr::V6(_) => return self.reply(status::CANNOT_OPEN_DATA_CONNECTION, "Use EPSV over IPv6"),
+        };
+        let port = try!(self.listen());
+        let text = format!("Entering Passive Mode ({},{},{},{},{},{})",
+                           octets[0], octets[1], octets[2], octets[3], port >> 8, port & 0xff);
+        self.reply(status::PASSIVE_MODE, &text)
+    }
+
+    fn epsv(&mut self, arg: &str) -> Handled {
+        if arg.eq_ignore_ascii_case("ALL") {
+            return self.reply(status::COMMAND_OK, "EPSV ALL ok");
+        }
+        let port = try!(self.listen());
+        self.reply(status::EXTENDED_PASSIVE_MODE, &format!("Entering Extended Passive Mode (|||{}|)", port))
+    }
+
+    /// Connects to the PORT address or waits for the client to connect to the passive port,
+    /// None once the client was told why not
+    ///
+    /// The `opening` reply comes before waiting in passive mode, so that the other server of
+    /// an FXP copy may be sent its command, and an ABOR gives up waiting.
+    fn accept_data(&mut self, opening: &str) -> stdio::Result<Option<TcpStream>> {
+        if let Some(addr) = self.active.take() {
+            return match TcpStream::connect_timeout(&addr, Duration::from_secs(DATA_TIMEOUT_SECS)) {
+                Ok(stream) => {
+                    try!(self.reply(status::ABOUT_TO_SEND, opening));
+                    Ok(Some(stream))
+                }
+                Err(_) => {
+                    try!(self.reply(status::CANNOT_OPEN_DATA_CONNECTION, "Can't open data connection"));
+                    Ok(None)
+                }
+            };
+        }
+        let listener = match self.passive.take() {
+            Some(listener) => listener,
+            None => {
+                try!(self.reply(status::CANNOT_OPEN_DATA_CONNECTION, "Use PORT, PASV or EPSV first"));
+                return Ok(None);
+            }
+        };
+        try!(self.reply(status::ABOUT_TO_SEND, opening));
+        try!(listener.set_nonblocking(true));
+        let started = Instant::now();
+        loop {
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    try!(stream.set_nonblocking(false));
+                    return Ok(Some(stream));
+                }
+                Err(ref err) if err.kind() == stdio::ErrorKind::WouldBlock => {
+                    if try!(self.abort_pending()) {
+                        let mut line = String::new();
+                        try!(self.reader.read_line(&mut line));
+                        try!(self.reply(status::TRANSER_ABORTED, "Connection closed, transfer aborted"));
+                        try!(self.reply(status::CLOSING_DATA_CONNECTION, "ABOR successful"));
+                        return Ok(None);
+                    }
+                    if started.elapsed() > Duration::from_secs(DATA_TIMEOUT_SECS) {
+                        try!(self.reply(status::CANNOT_OPEN_DATA_CONNECTION, "Data connection timed out"));
+                        return Ok(None);
+                    }
+                    thread::sleep(Duration::from_millis(5));
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Whether the client sent ABOR while waiting for a data connection, without waiting for it
+    fn abort_pending(&mut self) -> stdio::Result<bool> {
+        try!(self.reader.get_ref().set_nonblocking(true));
+        let pending = match self.reader.fill_buf() {
+            Ok(buf) => Ok(buf.len() >= 4 && buf[..4].eq_ignore_ascii_case(b"ABOR")),
+            Err(ref err) if err.kind() == stdio::ErrorKind::WouldBlock => Ok(false),
+            Err(err) => Err(err),
+        };
+        try!(self.reader.get_ref().set_nonblocking(false));
+        pending
+    }
+
+    /// Sends `data` over a new data connection, deflated in MODE Z
+    fn send_data<R: Read>(&mut self, opening: &str, data: &mut R) -> Handled {
+        let stream = match try!(self.accept_data(opening)) {
+            Some(stream) => stream,
+            None => return Ok(Next::Continue),
+        };
+
+        let sent = if self.compressed {
+            let mut encoder = ZlibEncoder::new(stream, Compression::default());
+            copy(data, &mut encoder).and_then(|_| encoder.finish()).map(|_| ())
+        } else {
+            let mut stream = stream;
+            copy(data, &mut stream).map(|_| ())
+        };
+        match sent {
+            Ok(()) => self.reply(status::CLOSING_DATA_CONNECTION, "Transfer complete"),
+            Err(_) => self.reply(status::TRANSER_ABORTED, "Connection closed, transfer aborted"),
+        }
+    }
+
+    fn retr(&mut self, arg: &str) -> Handled {
+        let path = self.path(arg);
+        let rest = self.rest;
+        self.rest = 0;
+        let opened = self.server.fs.metadata(&path).and_then(|entry| {
+            if entry.is_dir() {
+                return Err(stdio::Error::new(stdio::ErrorKind::Other, "Is a directory"));
+            }
+            let mut file = try!(self.server.fs.open(&path));
+            try!(copy(&mut (&mut file).take(rest), &mut sink()));
+            Ok((entry, file))
+        });
+        match opened {
+            Ok((entry, mut file)) => {
+                let size = entry.size.unwrap_or(0).saturating_sub(rest);
+                let opening = format!("Opening BINARY mode data connection for {} ({} bytes)", arg, size);
+                self.send_data(&opening, &mut file)
+            }
+            Err(err) => self.fail(err),
+        }
+    }
+
+    fn store(&mut self, arg: &str, append: bool) -> Handled {
+        let path = self.path(arg);
+        let opening = format!("Ok to send data for {}", arg);
+        self.receive(&path, append, &opening, "Transfer complete")
+    }
+
+    /// STOU, the argument if any is used as the base of the new name
+    fn store_unique(&mut self, arg: &str) -> Handled {
+        let base = if arg.is_empty() { "file" } else { arg };
+        let name = (0..).map(|n| if n == 0 { base.to_string() } else { format!("{}.{}", base, n) })
+            .find(|name| self.server.fs.metadata(&self.path(name)).is_err())
+            .unwrap();
+        let path = self.path(&name);
+        let opening = format!("FILE: {}", name);
+        let closing = format!("Transfer complete (unique file name: {})", name);
+        self.receive(&path, false, &opening, &closing)
+    }
+
+    /// Writes the data of a new data connection into `path`, inflated in MODE Z
+    fn receive(&mut self, path: &str, append: bool, opening: &str, closing: &str) -> Handled {
+        let rest = self.rest;
+        self.rest = 0;
+        let created = match self.server.fs.metadata(path) {
+            Ok(ref entry) if entry.is_dir() => Err(stdio::Error::new(stdio::ErrorKind::Other, "Is a directory")),
+            // a restarted upload goes on from where the file stops
+            _ => self.server.fs.create(path, append || rest > 0),
+        };
+        let mut file = match created {
+            Ok(file) => file,
+            Err(err) => return self.fail(err),
+        };
+        let stream = match try!(self.accept_data(opening)) {
+            Some(stream) => stream,
+            None => return Ok(Next::Continue),
+        };
+
+        let received = if self.compressed {
+            copy(&mut ZlibDecoder::new(stream), &mut file)
+        } else {
+            let mut stream = stream;
+            copy(&mut stream, &mut file)
+        };
+        let received = received.and_then(|_| file.flush());
+        drop(file);
+        match received {
+            Ok(()) => self.reply(status::CLOSING_DATA_CONNECTION, closing),
+            Err(err) => self.reply(status::ACTION_ABORTED, &format!("Transfer failed: {}", err)),
+        }
+    }
+
+    fn list(&mut self, arg: &str, listing: Listing) -> Handled {
+        // LIST and NLST options like `-a` are accepted and ignored
+        let arg = arg.split_whitespace().filter(|word| !word.starts_with('-')).collect::<Vec<&str>>().join(" ");
+        let path = self.path(&arg);
+        let entries = self.server.fs.metadata(&path).and_then(|entry| {
+            if entry.is_dir() {
+                self.server.fs.read_dir(&path)
+            } else if listing == Listing::Facts {
+                Err(stdio::Error::new(stdio::ErrorKind::Other, "Not a directory"))
+            } else {
+                Ok(vec![entry])
+            }
+        });
+        let entries = match entries {
+            Ok(entries) => entries,
+            Err(err) => return self.fail(err),
+        };
+
+        let now = UTC::now();
+        let mut text = String::new();
+        for entry in &entries {
+            let line = match listing {
+                Listing::Long => long_line(entry, &now),
+                Listing::Names => entry.name.clone(),
+                Listing::Facts => format!("{}{}", facts(entry), entry.name),
+            };
+            text.push_str(&line);
+            text.push_str("\r\n");
+        }
+        self.send_data("Here comes the directory listing", &mut text.as_bytes())
+    }
+
+    fn size(&mut self, arg: &str) -> Handled {
+        let path = self.path(arg);
+        match self.server.fs.metadata(&path) {
+            Ok(ref entry) if entry.is_file() => {
+                let size = entry.size.unwrap_or(0);
+                self.reply(status::FILE, &size.to_string())
+            }
+            Ok(_) => self.reply(status::FILE_UNAVAILABLE, "Not a regular file"),
+            Err(err) => self.fail(err),
+        }
+    }
+
+    /// MDTM, or its variant setting the time when the first argument is one
+    fn mdtm(&mut self, arg: &str) -> Handled {
+        if let Some((time, path)) = split_time(arg) {
+            if self.server.read_only {
+                return self.reply(status::FILE_UNAVAILABLE, "Permission denied");
+            }
+            return self.set_modified(&time, path);
+        }
+
+        let path = self.path(arg);
+        match self.server.fs.metadata(&path) {
+            Ok(entry) => match entry.modified {
+                Some(modified) => self.reply(status::FILE, &modified.format("%Y%m%d%H%M%S").to_string()),
+                None => self.reply(status::FILE_UNAVAILABLE, "Modification time unknown"),
+            },
+            Err(err) => self.fail(err),
+        }
+    }
+
+    fn mfmt(&mut self, arg: &str) -> Handled {
+        match split_time(arg) {
+            Some((time, path)) => self.set_modified(&time, path),
+            None => self.reply(status::BAD_ARGUMENTS, "Expected a time and a path"),
+        }
+    }
+
+    fn set_modified(&mut self, time: &DateTime<UTC>, arg: &str) -> Handled {
+        let path = self.path(arg);
+        match self.server.fs.set_modified(&path, time) {
+            Ok(()) => self.reply(status::FILE, &format!("Modify={}; {}", time.format("%Y%m%d%H%M%S"), arg)),
+            Err(err) => self.fail(err),
+        }
+    }
+
+    /// The size and checksum of a file
+    fn checksum(&self, path: &str, algorithm: Algorithm) -> stdio::Result<(u64, String)> {
+        let mut file = try!(self.server.fs.open(path));
+        let mut hasher = Hasher::new(algorithm);
+        let mut buf = [0; 8192];
+        let mut size = 0;
+        loop {
+            let n = try!(file.read(&mut buf));
+            if n == 0 {
+                return Ok((size, hasher.finish()));
+            }
+            size += n as u64;
+            hasher.update(&buf[..n]);
+        }
+    }
+
+    fn site(&mut self, arg: &str) -> Handled {
+        let mut words = arg.splitn(3, ' ');
+        let command = words.next().unwrap_or("").to_uppercase();
+        let (first, rest) = (words.next(), words.next());
+        match (&command[..], first, rest) {
+            ("CHMOD", Some(mode), Some(path)) if !self.server.read_only => {
+                let path = self.path(path);
+                match u32::from_str_radix(mode, 8) {
+                    Ok(mode) => match self.server.fs.set_mode(&path, mode) {
+                        Ok(()) => self.reply(status::COMMAND_OK, "SITE CHMOD command ok"),
+                        Err(err) => self.fail(err),
+                    },
+                    Err(_) => self.reply(status::BAD_ARGUMENTS, "Invalid mode"),
+                }
+            }
+            ("CHMOD", _, _) if self.server.read_only => self.reply(status::FILE_UNAVAILABLE, "Permission denied"),
+            ("UMASK", None, _) => {
+                let text = format!("Your current UMASK is {:03o}", self.umask);
+                self.reply(status::COMMAND_OK, &text)
+            }
+            ("UMASK", Some(umask), _) => match u32::from_str_radix(umask, 8) {
+                Ok(umask) => {
+                    self.umask = umask;
+                    self.reply(status::COMMAND_OK, &format!("UMASK set to {:03o}", umask))
+                }
+                Err(_) => self.reply(status::BAD_ARGUMENTS, "Invalid umask"),
+            },
+            ("IDLE", None, _) => {
+                let text = format!("Current idle time limit is {} seconds", self.idle);
+                self.reply(status::COMMAND_OK, &text)
+            }
+            ("IDLE", Some(secs), _) => match secs.parse() {
+                Ok(secs) => {
+                    self.idle = secs;
+                    self.reply(status::COMMAND_OK, &format!("Maximum idle time set to {} seconds", secs))
+                }
+                Err(_) => self.reply(status::BAD_ARGUMENTS, "Invalid idle time"),
+            },
+            ("HELP", _, _) => {
+                let commands = vec![String::from("CHMOD UMASK IDLE HELP")];
+                self.reply_lines(status::HELP, "The following SITE commands are recognized", &commands, "Help OK")
+            }
+            _ => self.reply(status::BAD_COMMAND, "Unknown SITE command"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Listing {
+    /// LIST, in the Unix `ls -l` format
+    Long,
+    /// NLST
+    Names,
+    /// MLSD
+    Facts,
+}
+
+fn is_anonymous(user: &str) -> bool {
+    user == "anonymous" || user == "ftp"
+}
+
+/// Parses the `h1,h2,h3,h4,p1,p2` address of PORT
+fn parse_port(arg: &str) -> Option<SocketAddr> {
+    let numbers: Vec<u8> = arg.trim().split(',').filter_map(|n| n.trim().parse().ok()).collect();
+    if numbers.len() != 6 {
+        return None;
+    }
+    let ip = IpAddr::from([numbers[0], numbers[1], numbers[2], numbers[3]]);
+    Some(SocketAddr::new(ip, (numbers[4] as u16) << 8 | numbers[5] as u16))
+}
+
+/// Splits `YYYYMMDDHHMMSS path`, as sent to MFMT and to MDTM setting a time
+fn split_time(arg: &str) -> Option<(DateTime<UTC>, &str)> {
+    let space = match arg.find(' ') {
+        Some(space) if space == 14 => space,
+        _ => return None,
+    };
+    let (time, path) = (&arg[..space], &arg[space + 1..]);
+    if !time.chars().all(|c| c.is_digit(10)) || path.is_empty() {
+        return None;
+    }
+    UTC.datetime_from_str(time, "%Y%m%d%H%M%S").ok().map(|time| (time, path))
+}
+
+/// The MLSD facts of an entry, ending with the space before the name
+fn facts(entry: &Entry) -> String {
+    let mut facts = match entry.entry_type {
+        EntryType::File => String::from("type=file;"),
+        EntryType::Dir => String::from("type=dir;"),
+        EntryType::Symlink => format!("type=OS.unix=slink:{};", entry.target.as_ref().map_or("", |target| &target[..])),
+        EntryType::Other => String::from("type=OS.unix=special;"),
+    };
+    if let (true, Some(size)) = (entry.is_file(), entry.size) {
+        facts.push_str(&format!("size={};", size));
+    }
+    if let Some(modified) = entry.modified {
+        facts.push_str(&format!("modify={};", modified.format("%Y%m%d%H%M%S")));
+    }
+    if let Some(mode) = entry.mode {
+        facts.push_str(&format!("unix.mode={:04o};", mode));
+    }
+    if let Some(ref unique) = entry.unique {
+        facts.push_str(&format!("unique={};", unique));
+    }
+    facts.push(' ');
+    facts
+}
+
+/// A LIST line in the Unix `ls -l` format, times older than half a year show the year
+fn long_line(entry: &Entry, now: &DateTime<UTC>) -> String {
+    let kind = match entry.entry_type {
+        EntryType::File => '-',
+        EntryType::Dir => 'd',
+        EntryType::Symlink => 'l',
+        EntryType::Other => '?',
+    };
+    let mode = entry.mode.unwrap_or(if entry.is_dir() { 0o755 } else { 0o644 });
+    let perms: String = "rwxrwxrwx".chars().enumerate()
+        .map(|(i, c)| if mode & (1 << (8 - i)) != 0 { c } else { '-' })
+        .collect();
+    let modified = entry.modified.unwrap_or(*now);
+    let date = if (*now - modified).num_days().abs() < 180 {
+        modified.format("%b %e %H:%M").to_string()
+    } else {
+        modified.format("%b %e  %Y").to_string()
+    };
+    let name = match entry.target {
+        Some(ref target) if entry.is_symlink() => format!("{} -> {}", entry.name, target),
+        _ => entry.name.clone(),
+    };
+    format!("{}{} 1 ftp ftp {:>10} {} {}", kind, perms, entry.size.unwrap_or(0), date, name)
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,88 @@
+use std::env;
+use std::fs;
+use std::io::Read;
+use std::net::TcpListener;
+use std::path::PathBuf;
+use super::{Server, LocalFs};
+use super::super::FtpStream;
+
+/// A fresh directory under the system's temporary directory
+fn scratch(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("zftpd-{}-{}", name, ::std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("pub")).unwrap();
+    fs::write(dir.join("pub/notes.txt"), b"notes").unwrap();
+    dir
+}
+
+fn serve(server: Server) -> FtpStream {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    server.spawn(listener);
+    FtpStream::connect_quiet(addr).unwrap()
+}
+
+#[test]
+fn local_directory() {
+    let dir = scratch("local");
+    let mut ftp = serve(Server::new(LocalFs::new(dir.clone())).account("user", "secret"));
+    ftp.login("user", "secret").unwrap();
+
+    ftp.cwd("pub").unwrap();
+    assert_eq!(ftp.nlist(None).unwrap(), vec!["notes.txt"]);
+    let mut data = String::new();
+    {
+        let mut stream = ftp.get("notes.txt").unwrap();
+        stream.read_to_string(&mut data).unwrap();
+        stream.finish().unwrap();
+    }
+    assert_eq!(data, "notes");
+
+    ftp.put("new.txt", &mut &b"new"[..]).unwrap();
+    ftp.append("new.txt", &mut &b" file"[..]).unwrap();
+    assert_eq!(fs::read(dir.join("pub/new.txt")).unwrap(), b"new file");
+    ftp.rename("new.txt", "renamed.txt").unwrap();
+    ftp.rm("renamed.txt").unwrap();
+    ftp.mkdir("sub").unwrap();
+    assert!(dir.join("pub/sub").is_dir());
+    ftp.rmdir("sub").unwrap();
+
+    // the root can't be left
+    ftp.cwd("../../..").unwrap();
+    assert_eq!(ftp.pwd().unwrap(), "/");
+    assert_eq!(ftp.nlist(None).unwrap(), vec!["pub"]);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn read_only_and_anonymous() {
+    let dir = scratch("read-only");
+    let server = Server::new(LocalFs::new(dir.clone())).anonymous(true).read_only(true);
+    let mut ftp = serve(server.clone());
+    assert!(ftp.login("user", "secret").is_err());
+    ftp.login("anonymous", "guest@example.com").unwrap();
+
+    assert_eq!(ftp.size("pub/notes.txt").unwrap(), Some(5));
+    assert!(ftp.put("pub/new.txt", &mut &b"new"[..]).is_err());
+    assert!(ftp.rm("pub/notes.txt").is_err());
+    assert!(ftp.mkdir("sub").is_err());
+    assert!(ftp.rename("pub", "other").is_err());
+    assert!(!dir.join("pub/new.txt").exists());
+    assert!(dir.join("pub/notes.txt").exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn port_to_another_host() {
+    let dir = scratch("bounce");
+    let foreign = "10.0.0.1:2121".parse().unwrap();
+    let mut ftp = serve(Server::new(LocalFs::new(dir.clone())).anonymous(true));
+    ftp.login("anonymous", "guest@example.com").unwrap();
+    assert!(ftp.port(foreign).is_err());
+    assert!(ftp.port("127.0.0.1:2121".parse().unwrap()).is_ok());
+
+    let mut ftp = serve(Server::new(LocalFs::new(dir.clone())).anonymous(true).fxp(true));
+    ftp.login("anonymous", "guest@example.com").unwrap();
+    ftp.port(foreign).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+}
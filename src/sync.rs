@@ -0,0 +1,500 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::fs::{self, File};
+use std::io as stdio;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use chrono::{DateTime, UTC, TimeZone};
+use super::ftp::FtpStream;
+use super::engine::{TransferEngine, Job, JobResult};
+use super::glob::Filter;
+use super::listing::Entry;
+use super::mirror::same_time;
+use super::walk::{join, walk_local, WalkEntry};
+use super::types::{FtpError, Result};
+
+/// Name of the state file kept in the local directory
+pub const STATE_FILE: &'static str = ".zftp-sync";
+
+/// Size and modification time of a file when it was last looked at
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stamp {
+    pub size: Option<u64>,
+    pub modified: Option<DateTime<UTC>>,
+}
+
+impl Stamp {
+    fn of(entry: &Entry) -> Stamp {
+        Stamp { size: entry.size, modified: entry.modified }
+    }
+
+    /// Whether both describe the same version of a file
+    pub fn same(&self, other: &Stamp) -> bool {
+        self.size == other.size && same_time(&self.modified, &other.modified)
+    }
+}
+
+impl fmt::Display for Stamp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.size {
+            Some(size) => try!(write!(f, "{} bytes", size)),
+            None => try!(write!(f, "unknown size")),
+        }
+        match self.modified {
+            Some(time) => write!(f, ", modified {}", time.format("%Y-%m-%d %H:%M:%S UTC")),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Both sides of a file as they were after the last sync
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Synced {
+    pub local: Stamp,
+    pub remote: Stamp,
+}
+
+/// The files seen by the last sync, stored one per line in the state file
+#[derive(Debug, Clone, Default)]
+pub struct State {
+    pub files: HashMap<String, Synced>,
+}
+
+impl State {
+    /// Reads a state file, a missing file gives an empty state
+    pub fn load<P: AsRef<Path>>(path: P) -> stdio::Result<State> {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(ref err) if err.kind() == stdio::ErrorKind::NotFound => return Ok(State::default()),
+            Err(err) => return Err(err),
+        };
+
+        let mut state = State::default();
+        for line in BufReader::new(file).lines() {
+            let line = try!(line);
+            if line.starts_with('#') {
+                continue;
+            }
+            // local size, local time, remote size, remote time and the path, which may contain tabs
+            let fields: Vec<&str> = line.splitn(5, '\t').collect();
+            if fields.len() != 5 {
+                return Err(stdio::Error::new(stdio::ErrorKind::InvalidData,
+                                             format!("invalid sync state line: {}", line)));
+            }
+            let synced = Synced {
+                local: Stamp { size: fields[0].parse().ok(), modified: parse_time(fields[1]) },
+                remote: Stamp { size: fields[2].parse().ok(), modified: parse_time(fields[3]) },
+            };
+            state.files.insert(fields[4].to_string(), synced);
+        }
+        Ok(state)
+    }
+
+    /// Writes the state file, replacing it only once it is complete
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> stdio::Result<()> {
+        let path = path.as_ref();
+        if let Some(dir) = path.parent() {
+            try!(fs::create_dir_all(dir));
+        }
+        let partial = path.with_extension("partial");
+        {
+            let mut out = BufWriter::new(try!(File::create(&partial)));
+            try!(writeln!(out, "# zftp sync state: local size, local time, remote size, remote time, path"));
+            let paths: BTreeSet<&String> = self.files.keys().collect();
+            for path in paths {
+                let synced = &self.files[path];
+                try!(writeln!(out, "{}\t{}\t{}\t{}\t{}", show_size(synced.local.size), show_time(synced.local.modified),
+                              show_size(synced.remote.size), show_time(synced.remote.modified), path));
+            }
+            try!(out.flush());
+        }
+        fs::rename(&partial, path)
+    }
+}
+
+/// How files changed on both sides since the last sync are handled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Policy {
+    /// The most recently modified version replaces the other
+    Newer,
+    /// The remote version is kept next to the local one under a suffixed name, on both sides
+    KeepBoth,
+    /// Each conflict is decided by the callback given to `TwoWaySync::plan`
+    Ask,
+}
+
+/// The decision taken on a `Conflict`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    Local,
+    Remote,
+    KeepBoth,
+    Skip,
+}
+
+/// A file changed on both sides since the last sync, a missing stamp means it was deleted
+#[derive(Debug, Clone)]
+pub struct Conflict {
+    pub path: String,
+    pub local: Option<Stamp>,
+    pub remote: Option<Stamp>,
+}
+
+/// A step of a `SyncPlan`, paths are relative to the synced directories
+#[derive(Debug, Clone)]
+pub enum SyncAction {
+    Upload(String),
+    Download(String),
+    DeleteLocal(String),
+    DeleteRemote(String),
+    /// Renames the remote file to `copy`, fetches that copy and sends the local file
+    KeepBoth { path: String, copy: String },
+}
+
+impl SyncAction {
+    fn path(&self) -> &str {
+        match *self {
+            SyncAction::Upload(ref path) | SyncAction::Download(ref path) |
+            SyncAction::DeleteLocal(ref path) | SyncAction::DeleteRemote(ref path) |
+            SyncAction::KeepBoth { ref path, .. } => path,
+        }
+    }
+}
+
+impl fmt::Display for SyncAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SyncAction::Upload(ref path) => write!(f, "put            {}", path),
+            SyncAction::Download(ref path) => write!(f, "get            {}", path),
+            SyncAction::DeleteLocal(ref path) => write!(f, "delete local   {}", path),
+            SyncAction::DeleteRemote(ref path) => write!(f, "delete remote  {}", path),
+            SyncAction::KeepBoth { ref path, ref copy } => write!(f, "keep both      {} (remote as {})", path, copy),
+        }
+    }
+}
+
+/// Keeps a local and a remote directory in step, both being edited
+///
+/// A state file in the local directory remembers every file as of the last sync, so a
+/// file changed on one side only is copied over, a deleted one is deleted on the other
+/// side, and one changed on both sides is a conflict settled by the `Policy`. Only files
+/// are synced, directories are created as needed and never deleted.
+#[derive(Debug, Clone)]
+pub struct TwoWaySync {
+    local: String,
+    remote: String,
+    state_file: PathBuf,
+    filter: Filter,
+    policy: Policy,
+}
+
+impl TwoWaySync {
+    pub fn new(local: &str, remote: &str) -> TwoWaySync {
+        TwoWaySync {
+            local: local.to_string(),
+            remote: remote.to_string(),
+            state_file: Path::new(local).join(STATE_FILE),
+            filter: Filter::new(),
+            policy: Policy::Newer,
+        }
+    }
+
+    /// Keeps the state somewhere else than in the local directory
+    pub fn state_file<P: AsRef<Path>>(mut self, path: P) -> TwoWaySync {
+        self.state_file = path.as_ref().to_path_buf();
+        self
+    }
+
+    pub fn filter(mut self, filter: Filter) -> TwoWaySync {
+        self.filter = filter;
+        self
+    }
+
+    /// Sets how conflicts are settled, `Policy::Newer` by default
+    pub fn policy(mut self, policy: Policy) -> TwoWaySync {
+        self.policy = policy;
+        self
+    }
+
+    /// Compares both sides with the state of the last sync, without changing anything
+    ///
+    /// `ask` is only called with `Policy::Ask`, once per conflict.
+    pub fn plan<F>(&self, ftp: &mut FtpStream, mut ask: F) -> Result<SyncPlan>
+        where F: FnMut(&Conflict) -> Resolution {
+        let state = try!(State::load(&self.state_file));
+        let (local, remote) = try!(self.scan(ftp));
+
+        let mut paths: BTreeSet<&String> = local.keys().collect();
+        paths.extend(remote.keys());
+
+        let mut actions = Vec::new();
+        let mut conflicts = Vec::new();
+        let mut skipped = HashSet::new();
+        for path in paths {
+            let (l, r) = (local.get(path).cloned(), remote.get(path).cloned());
+            let synced = state.files.get(path);
+            let local_changed = changed(l, synced.map(|synced| synced.local));
+            let remote_changed = changed(r, synced.map(|synced| synced.remote));
+
+            let action = match (l, r) {
+                (None, None) => None,
+                (Some(_), None) if synced.is_none() => Some(SyncAction::Upload(path.clone())),
+                (None, Some(_)) if synced.is_none() => Some(SyncAction::Download(path.clone())),
+                // both sides ended up with the same version
+                (Some(l), Some(r)) if l.same(&r) => None,
+                _ if !local_changed && !remote_changed => None,
+                (None, Some(_)) if !remote_changed => Some(SyncAction::DeleteRemote(path.clone())),
+                (Some(_), None) if !local_changed => Some(SyncAction::DeleteLocal(path.clone())),
+                (Some(_), _) if !remote_changed => Some(SyncAction::Upload(path.clone())),
+                (_, Some(_)) if !local_changed => Some(SyncAction::Download(path.clone())),
+                _ => {
+                    let conflict = Conflict { path: path.clone(), local: l, remote: r };
+                    let resolution = match self.policy {
+                        Policy::Newer => newer(&conflict),
+                        Policy::KeepBoth => Resolution::KeepBoth,
+                        Policy::Ask => ask(&conflict),
+                    };
+                    conflicts.push(conflict);
+                    match (resolution, l, r) {
+                        (Resolution::Skip, _, _) => {
+                            skipped.insert(path.clone());
+                            None
+                        }
+                        // a deleted side has nothing to keep
+                        (_, None, _) => Some(SyncAction::Download(path.clone())),
+                        (_, _, None) => Some(SyncAction::Upload(path.clone())),
+                        (Resolution::Local, _, _) => Some(SyncAction::Upload(path.clone())),
+                        (Resolution::Remote, _, _) => Some(SyncAction::Download(path.clone())),
+                        (Resolution::KeepBoth, _, _) => {
+                            let copy = conflict_name(path, &local, &remote);
+                            Some(SyncAction::KeepBoth { path: path.clone(), copy: copy })
+                        }
+                    }
+                }
+            };
+            if let Some(action) = action {
+                actions.push(action);
+            }
+        }
+
+        Ok(SyncPlan {
+            sync: self.clone(),
+            state: state,
+            actions: actions,
+            conflicts: conflicts,
+            skipped: skipped,
+        })
+    }
+
+    /// The files on each side, by path relative to the synced directories
+    fn scan(&self, ftp: &mut FtpStream) -> Result<(HashMap<String, Stamp>, HashMap<String, Stamp>)> {
+        let local = if Path::new(&self.local).is_dir() {
+            try!(walk_local(&self.local))
+        } else {
+            Vec::new()
+        };
+        let remote = if try!(ftp.is_dir(&self.remote)) {
+            try!(ftp.walk(&self.remote).collect::<Result<Vec<WalkEntry>>>())
+        } else {
+            Vec::new()
+        };
+
+        let partial = self.state_file.with_extension("partial");
+        let files = |entries: Vec<WalkEntry>| -> HashMap<String, Stamp> {
+            entries.into_iter()
+                .filter(|item| item.entry.is_file() && self.filter.matches(&item.relative, false))
+                .map(|item| (item.relative, Stamp::of(&item.entry)))
+                .collect()
+        };
+        let local = local.into_iter().filter(|item| {
+            let path = Path::new(&item.path);
+            path != self.state_file && path != partial
+        }).collect();
+        Ok((files(local), files(remote)))
+    }
+}
+
+/// The outcome of a sync, see `SyncPlan::execute`
+#[derive(Debug)]
+pub struct SyncOutcome {
+    pub results: Vec<JobResult>,
+    /// The renames, deletions and directories which failed
+    pub errors: Vec<(SyncAction, FtpError)>,
+}
+
+/// The steps bringing both sides in step, returned by `TwoWaySync::plan`
+#[derive(Debug, Clone)]
+pub struct SyncPlan {
+    sync: TwoWaySync,
+    state: State,
+    pub actions: Vec<SyncAction>,
+    /// Every conflict met, whatever its resolution
+    pub conflicts: Vec<Conflict>,
+    skipped: HashSet<String>,
+}
+
+impl SyncPlan {
+    /// The transfers of the plan
+    pub fn jobs(&self) -> Vec<Job> {
+        let mut jobs = Vec::new();
+        for action in &self.actions {
+            match *action {
+                SyncAction::Upload(ref path) => jobs.push(Job::put(&self.local(path), &self.remote(path))),
+                SyncAction::Download(ref path) => jobs.push(Job::get(&self.remote(path), &self.local(path))),
+                SyncAction::KeepBoth { ref path, ref copy } => {
+                    jobs.push(Job::get(&self.remote(copy), &self.local(copy)));
+                    jobs.push(Job::put(&self.local(path), &self.remote(path)));
+                }
+                _ => (),
+            }
+        }
+        jobs
+    }
+
+    /// Runs the plan through `engine` and records the new state
+    ///
+    /// Files whose step failed keep their previous state, so the next sync tries again.
+    pub fn execute(&self, ftp: &mut FtpStream, engine: &TransferEngine) -> Result<SyncOutcome> {
+        let mut errors = Vec::new();
+        let mut failed: HashSet<String> = self.skipped.clone();
+        let mut remote_dirs = HashSet::new();
+
+        for action in &self.actions {
+            if let Err(err) = self.prepare(ftp, action, &mut remote_dirs) {
+                failed.insert(action.path().to_string());
+                if let SyncAction::KeepBoth { ref copy, .. } = *action {
+                    failed.insert(copy.clone());
+                }
+                errors.push((action.clone(), err));
+            }
+        }
+        let jobs: Vec<Job> = self.jobs().into_iter()
+            .filter(|job| !failed.contains(self.relative(job.local())))
+            .collect();
+        let results = engine.run(jobs);
+        for result in results.iter().filter(|result| result.result.is_err()) {
+            failed.insert(self.relative(result.job.local()).to_string());
+        }
+
+        // both sides are listed again, the transfers changed their times
+        let (local, remote) = try!(self.sync.scan(ftp));
+        let mut state = State::default();
+        for (path, l) in &local {
+            if failed.contains(path) {
+                continue;
+            }
+            if let Some(r) = remote.get(path) {
+                state.files.insert(path.clone(), Synced { local: *l, remote: *r });
+            }
+        }
+        for path in &failed {
+            if let Some(synced) = self.state.files.get(path) {
+                state.files.insert(path.clone(), *synced);
+            }
+        }
+        try!(state.save(&self.sync.state_file));
+
+        Ok(SyncOutcome { results: results, errors: errors })
+    }
+
+    /// Does the part of an action happening before the transfers
+    fn prepare(&self, ftp: &mut FtpStream, action: &SyncAction, remote_dirs: &mut HashSet<String>) -> Result<()> {
+        match *action {
+            SyncAction::DeleteLocal(ref path) => fs::remove_file(self.local(path)).map_err(FtpError::from),
+            SyncAction::DeleteRemote(ref path) => ftp.rm(&self.remote(path)),
+            SyncAction::Download(ref path) => self.local_parent(path),
+            SyncAction::Upload(ref path) => self.remote_parent(ftp, path, remote_dirs),
+            SyncAction::KeepBoth { ref path, ref copy } => {
+                try!(ftp.rename(&self.remote(path), &self.remote(copy)));
+                self.local_parent(copy)
+            }
+        }
+    }
+
+    fn local_parent(&self, path: &str) -> Result<()> {
+        match Path::new(&self.local(path)).parent() {
+            Some(parent) => fs::create_dir_all(parent).map_err(FtpError::from),
+            None => Ok(()),
+        }
+    }
+
+    fn remote_parent(&self, ftp: &mut FtpStream, path: &str, made: &mut HashSet<String>) -> Result<()> {
+        let parent = match path.rfind('/') {
+            Some(slash) => self.remote(&path[..slash]),
+            None => self.sync.remote.clone(),
+        };
+        if made.contains(&parent) {
+            return Ok(());
+        }
+        try!(ftp.mkdir_all(&parent));
+        made.insert(parent);
+        Ok(())
+    }
+
+    fn local(&self, path: &str) -> String {
+        join(&self.sync.local, path)
+    }
+
+    fn remote(&self, path: &str) -> String {
+        join(&self.sync.remote, path)
+    }
+
+    /// The synced path of a local path built by `local`
+    fn relative<'a>(&self, local: &'a str) -> &'a str {
+        let root = join(&self.sync.local, "");
+        if local.starts_with(&root) { &local[root.len()..] } else { local }
+    }
+}
+
+fn changed(now: Option<Stamp>, before: Option<Stamp>) -> bool {
+    match (now, before) {
+        (Some(now), Some(before)) => !now.same(&before),
+        (None, None) => false,
+        _ => true,
+    }
+}
+
+/// Settles a conflict for the most recent version, a deletion counts as older than any change
+fn newer(conflict: &Conflict) -> Resolution {
+    match (conflict.local.and_then(|l| l.modified), conflict.remote.and_then(|r| r.modified)) {
+        (Some(l), Some(r)) if r > l => Resolution::Remote,
+        (Some(_), Some(_)) => Resolution::Local,
+        _ if conflict.local.is_none() => Resolution::Remote,
+        _ => Resolution::Local,
+    }
+}
+
+/// `report.csv` becomes `report.conflict.csv`, then `report.conflict-2.csv` if that is taken
+fn conflict_name(path: &str, local: &HashMap<String, Stamp>, remote: &HashMap<String, Stamp>) -> String {
+    let name_start = path.rfind('/').map_or(0, |slash| slash + 1);
+    let (stem, ext) = match path[name_start..].rfind('.') {
+        Some(dot) if dot > 0 => path.split_at(name_start + dot),
+        _ => (path, ""),
+    };
+    let mut count = 1;
+    loop {
+        let copy = match count {
+            1 => format!("{}.conflict{}", stem, ext),
+            _ => format!("{}.conflict-{}{}", stem, count, ext),
+        };
+        if !local.contains_key(&copy) && !remote.contains_key(&copy) {
+            return copy;
+        }
+        count += 1;
+    }
+}
+
+fn show_size(size: Option<u64>) -> String {
+    size.map_or("-".to_string(), |size| size.to_string())
+}
+
+fn show_time(time: Option<DateTime<UTC>>) -> String {
+    time.map_or("-".to_string(), |time| time.timestamp().to_string())
+}
+
+fn parse_time(s: &str) -> Option<DateTime<UTC>> {
+    s.parse::<i64>().ok().map(|secs| UTC.timestamp(secs, 0))
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,87 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use chrono::{UTC, TimeZone};
+use super::{TwoWaySync, Policy, Resolution, Conflict, State, Stamp, Synced, STATE_FILE, conflict_name};
+use super::super::engine::TransferEngine;
+use super::super::mock::{MockServer, USER, PASSWORD};
+
+#[test]
+fn state() {
+    let path = env::temp_dir().join(format!("zftp-sync-state-{}", ::std::process::id()));
+    let local = Stamp { size: Some(5), modified: Some(UTC.timestamp(1577934245, 0)) };
+    let remote = Stamp { size: None, modified: None };
+    let mut state = State::default();
+    state.files.insert(String::from("docs/a file.txt"), Synced { local: local, remote: remote });
+    state.save(&path).unwrap();
+
+    let loaded = State::load(&path).unwrap();
+    assert_eq!(loaded.files, state.files);
+    fs::remove_file(&path).unwrap();
+    // there is no state before the first sync
+    assert!(State::load(&path).unwrap().files.is_empty());
+}
+
+#[test]
+fn conflict_names() {
+    let stamp = Stamp { size: Some(1), modified: None };
+    let mut taken = HashMap::new();
+    assert_eq!(conflict_name("docs/report.csv", &taken, &HashMap::new()), "docs/report.conflict.csv");
+    assert_eq!(conflict_name("Makefile", &taken, &HashMap::new()), "Makefile.conflict");
+    assert_eq!(conflict_name("v1.2/notes", &taken, &HashMap::new()), "v1.2/notes.conflict");
+    taken.insert(String::from("report.conflict.csv"), stamp);
+    assert_eq!(conflict_name("report.csv", &taken, &HashMap::new()), "report.conflict-2.csv");
+    assert_eq!(conflict_name("report.csv", &HashMap::new(), &taken), "report.conflict-2.csv");
+}
+
+#[test]
+fn sync() {
+    let dir = env::temp_dir().join(format!("zftp-sync-{}", ::std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("notes")).unwrap();
+    fs::write(dir.join("notes/local.txt"), b"local").unwrap();
+    let local = dir.to_str().unwrap();
+    let server = MockServer::start();
+    server.fs().add_file("share/remote.txt", b"remote");
+    let addr = server.addr();
+    let engine = TransferEngine::new(move || {
+        let mut ftp = try!(::FtpStream::connect_quiet(addr));
+        try!(ftp.login(USER, PASSWORD));
+        Ok(ftp)
+    });
+    let mut ftp = server.login();
+    let sync = TwoWaySync::new(local, "share").policy(Policy::KeepBoth);
+    let no_conflict = |_: &Conflict| -> Resolution { panic!("no conflict expected") };
+    let planned = |ftp: &mut ::FtpStream| -> Vec<String> {
+        sync.plan(ftp, no_conflict).unwrap().actions.iter().map(|action| action.to_string()).collect()
+    };
+
+    // planning changes nothing and writes no state
+    assert_eq!(planned(&mut ftp), vec!["put            notes/local.txt", "get            remote.txt"]);
+    assert!(!dir.join("remote.txt").exists());
+    assert!(!dir.join(STATE_FILE).exists());
+
+    sync.plan(&mut ftp, no_conflict).unwrap().execute(&mut ftp, &engine).unwrap();
+    assert_eq!(server.fs().contents("share/notes/local.txt").unwrap(), b"local");
+    assert_eq!(fs::read(dir.join("remote.txt")).unwrap(), b"remote");
+    assert_eq!(State::load(dir.join(STATE_FILE)).unwrap().files.len(), 2);
+    assert!(planned(&mut ftp).is_empty());
+
+    // deletions are carried over
+    fs::remove_file(dir.join("notes/local.txt")).unwrap();
+    assert_eq!(planned(&mut ftp), vec!["delete remote  notes/local.txt"]);
+    sync.plan(&mut ftp, no_conflict).unwrap().execute(&mut ftp, &engine).unwrap();
+    assert!(!server.fs().exists("share/notes/local.txt"));
+
+    // both versions of a file changed on both sides are kept on both sides
+    fs::write(dir.join("remote.txt"), b"edited here").unwrap();
+    server.fs().add_file("share/remote.txt", b"edited over there");
+    let plan = sync.plan(&mut ftp, no_conflict).unwrap();
+    assert_eq!(plan.conflicts.len(), 1);
+    assert_eq!(plan.actions[0].to_string(), "keep both      remote.txt (remote as remote.conflict.txt)");
+    plan.execute(&mut ftp, &engine).unwrap();
+    assert_eq!(fs::read(dir.join("remote.conflict.txt")).unwrap(), b"edited over there");
+    assert_eq!(server.fs().contents("share/remote.txt").unwrap(), b"edited here");
+    assert!(planned(&mut ftp).is_empty());
+    fs::remove_dir_all(&dir).unwrap();
+}
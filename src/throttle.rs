@@ -0,0 +1,106 @@
+use std::cmp;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+struct Bucket {
+    rate: u64,
+    allowance: f64,
+    last: Instant,
+}
+
+/// Limits the bandwidth of the transfers it is attached to, see `FtpStream::set_download_limit`
+///
+/// Clones share the same budget, so attaching clones of one limiter to several streams
+/// caps their combined rate. A rate of 0 means unlimited.
+#[derive(Clone)]
+pub struct RateLimiter {
+    bucket: Arc<Mutex<Bucket>>,
+}
+
+/// Parses a rate in bytes per second with an optional K, M or G suffix, `off` means unlimited
+pub fn parse_rate(rate: &str) -> Option<u64> {
+    if rate == "off" {
+        return Some(0);
+    }
+    let (number, unit) = match rate.chars().last() {
+        Some('k') | Some('K') => (&rate[..rate.len() - 1], 1024),
+        Some('m') | Some('M') => (&rate[..rate.len() - 1], 1024 * 1024),
+        Some('g') | Some('G') => (&rate[..rate.len() - 1], 1024 * 1024 * 1024),
+        _ => (rate, 1),
+    };
+    if number.is_empty() {
+        return None;
+    }
+    number.parse::<u64>().ok().and_then(|n| n.checked_mul(unit))
+}
+
+impl RateLimiter {
+    /// Creates a limiter allowing `rate` bytes per second
+    pub fn new(rate: u64) -> RateLimiter {
+        RateLimiter {
+            bucket: Arc::new(Mutex::new(Bucket {
+                rate: rate,
+                allowance: rate as f64,
+                last: Instant::now(),
+            })),
+        }
+    }
+
+    /// Bytes per second currently allowed, 0 when unlimited
+    pub fn rate(&self) -> u64 {
+        self.bucket.lock().unwrap().rate
+    }
+
+    /// Changes the rate for every stream sharing this limiter
+    pub fn set_rate(&self, rate: u64) {
+        let mut bucket = self.bucket.lock().unwrap();
+        bucket.rate = rate;
+        bucket.allowance = rate as f64;
+        bucket.last = Instant::now();
+    }
+
+    /// Largest amount of data worth moving at once, so transfers stay smooth
+    pub(crate) fn chunk(&self, wanted: usize) -> usize {
+        match self.rate() {
+            0 => wanted,
+            rate => {
+                let chunk = cmp::max(rate as usize / 10, 512);
+                cmp::min(chunk, wanted)
+            }
+        }
+    }
+
+    /// Accounts for `n` transferred bytes, sleeping when the budget is exhausted
+    pub fn consume(&self, n: usize) {
+        let wait = {
+            let mut bucket = self.bucket.lock().unwrap();
+            if bucket.rate == 0 {
+                return;
+            }
+            let now = Instant::now();
+            let elapsed = now.duration_since(bucket.last);
+            let elapsed = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1e9;
+            bucket.last = now;
+
+            // at most one second worth of data can be saved up
+            let rate = bucket.rate as f64;
+            bucket.allowance = (bucket.allowance + elapsed * rate).min(rate) - n as f64;
+            if bucket.allowance < 0.0 { -bucket.allowance / rate } else { 0.0 }
+        };
+
+        if wait > 0.0 {
+            thread::sleep(Duration::from_millis((wait * 1000.0) as u64));
+        }
+    }
+}
+
+impl fmt::Debug for RateLimiter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "RateLimiter({} bytes/s)", self.rate())
+    }
+}
+
+#[cfg(test)]
+mod tests;
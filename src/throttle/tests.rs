@@ -0,0 +1,62 @@
+use std::time::{Duration, Instant};
+use super::{RateLimiter, parse_rate};
+
+#[test]
+fn rates() {
+    assert_eq!(parse_rate("off"), Some(0));
+    assert_eq!(parse_rate("0"), Some(0));
+    assert_eq!(parse_rate("1500"), Some(1500));
+    assert_eq!(parse_rate("500k"), Some(500 * 1024));
+    assert_eq!(parse_rate("2M"), Some(2 * 1024 * 1024));
+    assert_eq!(parse_rate("1G"), Some(1024 * 1024 * 1024));
+}
+
+#[test]
+fn invalid_rates() {
+    assert_eq!(parse_rate(""), None);
+    assert_eq!(parse_rate("k"), None);
+    assert_eq!(parse_rate("M"), None);
+    assert_eq!(parse_rate("fast"), None);
+    assert_eq!(parse_rate("-5"), None);
+    assert_eq!(parse_rate("1.5M"), None);
+    // 2^64 / 1024 overflows once multiplied
+    assert_eq!(parse_rate("18014398509481984K"), None);
+    assert_eq!(parse_rate("18446744073709551615G"), None);
+}
+
+#[test]
+fn limits_the_rate() {
+    let limiter = RateLimiter::new(10_000);
+    let started = Instant::now();
+    // one second worth of data is allowed at once, the rest takes about a second
+    for _ in 0..20 {
+        limiter.consume(1000);
+    }
+    let elapsed = started.elapsed();
+    assert!(elapsed >= Duration::from_millis(900), "{:?}", elapsed);
+    assert!(elapsed < Duration::from_secs(3), "{:?}", elapsed);
+}
+
+#[test]
+fn clones_share_the_budget() {
+    let limiter = RateLimiter::new(10_000);
+    let other = limiter.clone();
+    limiter.consume(10_000);
+    let started = Instant::now();
+    other.consume(5_000);
+    assert!(started.elapsed() >= Duration::from_millis(400));
+
+    other.set_rate(0);
+    assert_eq!(limiter.rate(), 0);
+    let started = Instant::now();
+    limiter.consume(1_000_000);
+    assert!(started.elapsed() < Duration::from_millis(100));
+}
+
+#[test]
+fn chunks() {
+    assert_eq!(RateLimiter::new(0).chunk(65536), 65536);
+    assert_eq!(RateLimiter::new(100_000).chunk(65536), 10_000);
+    assert_eq!(RateLimiter::new(1000).chunk(65536), 512);
+    assert_eq!(RateLimiter::new(100_000).chunk(100), 100);
+}
@@ -1,13 +1,20 @@
 use std::fmt;
 use std::error::Error;
 use std::convert::From;
+use std::str::FromStr;
 
 #[derive(Debug)]
 pub enum FtpError {
     ConnectionError(::std::io::Error),
-    SecureError(String),         // not used
+    SecureError(String),
     InvalidResponse(String),
     InvalidAddress(::std::net::AddrParseError),
+    ChecksumMismatch(String),
+    InvalidUrl(String),
+    /// A local file could not be read or written, the connection is still usable
+    LocalError(::std::io::Error),
+    /// The server ended a transfer with 426 or 451, the connection is still usable
+    TransferAborted(Line),
 }
 
 impl From<::std::io::Error> for FtpError {
@@ -24,10 +31,49 @@ impl From<::std::net::AddrParseError> for FtpError {
 
 pub type Result<T> = ::std::result::Result<T, FtpError>;
 
+/// How a connection is secured with TLS
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tls {
+    /// Plain FTP
+    None,
+    /// TLS negotiated with AUTH TLS after connecting, usually on port 21
+    Explicit,
+    /// TLS from the start of the connection, usually on port 990
+    Implicit,
+}
+
+impl fmt::Display for Tls {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Tls::None => "none",
+            Tls::Explicit => "explicit",
+            Tls::Implicit => "implicit",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Tls {
+    type Err = String;
+
+    fn from_str(s: &str) -> ::std::result::Result<Tls, String> {
+        match &s.to_lowercase()[..] {
+            "none" | "off" => Ok(Tls::None),
+            "explicit" => Ok(Tls::Explicit),
+            "implicit" => Ok(Tls::Implicit),
+            _ => Err(format!("unknown TLS mode {}, expected none, explicit or implicit", s)),
+        }
+    }
+}
+
 /// `Line` contains a command code and the contents of a line of text read from network
 #[derive(Debug)]
 pub struct Line(pub u32, pub String);
 
+/// `Reply` contains a reply code and every line of a reply read from network
+#[derive(Debug)]
+pub struct Reply(pub u32, pub Vec<String>);
+
 impl fmt::Display for FtpError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
@@ -43,6 +89,18 @@ impl fmt::Display for FtpError {
             &FtpError::InvalidAddress(ref perr) =>  {
                 write!(f, "FTP InvalidAddress: {}", perr)
             },
+            &FtpError::ChecksumMismatch(ref desc) =>  {
+                write!(f, "FTP ChecksumMismatch: {}", desc.clone())
+            },
+            &FtpError::InvalidUrl(ref desc) =>  {
+                write!(f, "FTP InvalidUrl: {}", desc.clone())
+            },
+            &FtpError::LocalError(ref ioerr) =>  {
+                write!(f, "FTP LocalError: {}", ioerr)
+            },
+            &FtpError::TransferAborted(ref line) =>  {
+                write!(f, "FTP TransferAborted: {} {}", line.0, line.1)
+            },
         }
     }
 }
@@ -54,6 +112,10 @@ impl Error for FtpError {
             FtpError::SecureError(ref desc)         => desc.as_str(),
             FtpError::InvalidResponse(ref desc)     => desc.as_str(),
             FtpError::InvalidAddress(ref perr)      => perr.description(),
+            FtpError::ChecksumMismatch(ref desc)    => desc.as_str(),
+            FtpError::InvalidUrl(ref desc)          => desc.as_str(),
+            FtpError::LocalError(ref ioerr)         => ioerr.description(),
+            FtpError::TransferAborted(ref line)     => line.1.as_str(),
         }
     }
 
@@ -63,6 +125,10 @@ impl Error for FtpError {
             FtpError::SecureError(_) => None,
             FtpError::InvalidResponse(_) => None,
             FtpError::InvalidAddress(ref perr) => Some(perr),
+            FtpError::ChecksumMismatch(_) => None,
+            FtpError::InvalidUrl(_) => None,
+            FtpError::LocalError(ref ioerr) => Some(ioerr),
+            FtpError::TransferAborted(_) => None,
         }
     }
 }
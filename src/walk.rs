@@ -0,0 +1,320 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io as stdio;
+use std::path::{Component, Path, PathBuf};
+use std::vec;
+use super::ftp::FtpStream;
+use super::listing::{Entry, EntryType};
+use super::mtime::to_utc;
+use super::types::Result;
+
+/// An entry met while walking a remote tree
+#[derive(Debug, Clone)]
+pub struct WalkEntry {
+    /// Path of the entry, relative to the working directory like the walked path
+    pub path: String,
+    /// Path of the entry, relative to the walked directory
+    pub relative: String,
+    /// 1 for the entries of the walked directory, 2 for their children and so on
+    pub depth: usize,
+    pub entry: Entry,
+}
+
+/// A directory whose entries are being yielded
+struct Level {
+    entries: vec::IntoIter<Entry>,
+    path: String,
+    relative: String,
+    /// The path the server resolves this directory to, used to detect loops
+    real_path: String,
+    depth: usize,
+}
+
+/// A directory scheduled for listing
+struct Pending {
+    path: String,
+    relative: String,
+    real_path: String,
+    /// The MLSD `unique` fact of the directory
+    unique: Option<String>,
+    depth: usize,
+}
+
+/// A lazy depth first walk over a remote tree, returned by `FtpStream::walk`
+///
+/// A directory is only listed once the iterator moves past its entry, so stopping early
+/// saves the listings of the rest of the tree. Listing errors are yielded and the walk
+/// carries on with the next directory.
+pub struct Walk<'a> {
+    ftp: &'a mut FtpStream,
+    root: Option<String>,
+    levels: Vec<Level>,
+    /// Directory to list on the next step
+    pending: Option<Pending>,
+    visited: HashSet<String>,
+    max_depth: Option<usize>,
+    follow_symlinks: bool,
+    detect_loops: bool,
+}
+
+impl<'a> Walk<'a> {
+    pub(crate) fn new(ftp: &'a mut FtpStream, path: &str) -> Walk<'a> {
+        Walk {
+            ftp: ftp,
+            root: Some(path.to_string()),
+            levels: Vec::new(),
+            pending: None,
+            visited: HashSet::new(),
+            max_depth: None,
+            follow_symlinks: false,
+            detect_loops: true,
+        }
+    }
+
+    /// Stops descending below `depth`, a depth of 1 only yields the walked directory's entries
+    pub fn max_depth(mut self, depth: usize) -> Walk<'a> {
+        self.max_depth = Some(depth);
+        self
+    }
+
+    /// Descends into symbolic links pointing to directories, off by default
+    pub fn follow_symlinks(mut self, follow: bool) -> Walk<'a> {
+        self.follow_symlinks = follow;
+        self
+    }
+
+    /// Skips directories already visited through another path, on by default
+    ///
+    /// Only followed symbolic links can lead back to a visited directory. Directories are
+    /// told apart by the path links resolve to and by the MLSD `unique` fact, so servers listing
+    /// links as plain directories without `unique` facts need a `max_depth`.
+    pub fn detect_loops(mut self, detect: bool) -> Walk<'a> {
+        self.detect_loops = detect;
+        self
+    }
+
+    /// The path the server gives to the directory `path` links to, None if it isn't a directory
+    fn resolve(&mut self, path: &str) -> Result<Option<String>> {
+        let cwd = try!(self.ftp.pwd());
+        if self.ftp.cwd(path).is_err() {
+            return Ok(None);
+        }
+        let real_path = self.ftp.pwd();
+        try!(self.ftp.cwd(&cwd));
+        real_path.map(Some)
+    }
+
+    /// Lists `path` and makes its entries the next ones to yield
+    fn descend(&mut self, dir: Pending) -> Result<()> {
+        if self.detect_loops {
+            let seen = self.visited.contains(&dir.real_path) ||
+                dir.unique.as_ref().map_or(false, |unique| self.visited.contains(unique));
+            if seen {
+                return Ok(());
+            }
+            self.visited.insert(dir.real_path.clone());
+            if let Some(ref unique) = dir.unique {
+                self.visited.insert(unique.clone());
+            }
+        }
+        let entries = try!(self.ftp.entries(Some(&dir.path)));
+        self.levels.push(Level {
+            entries: entries.into_iter(),
+            path: dir.path,
+            relative: dir.relative,
+            real_path: dir.real_path,
+            depth: dir.depth,
+        });
+        Ok(())
+    }
+
+    /// Decides whether the walk goes into `entry`, met in the current directory
+    fn schedule(&mut self, entry: &WalkEntry) -> Result<()> {
+        if self.max_depth.map_or(false, |max| entry.depth >= max) {
+            return Ok(());
+        }
+        let parent_real = match self.levels.last() {
+            Some(level) => level.real_path.clone(),
+            None => return Ok(()),
+        };
+
+        let (real_path, unique) = if entry.entry.is_dir() {
+            (join(&parent_real, &entry.entry.name), entry.entry.unique.clone())
+        } else if entry.entry.is_symlink() && self.follow_symlinks {
+            // the link is only followed when it leads to a directory
+            // and its own `unique` fact may describe the link rather than the directory
+            match try!(self.resolve(&entry.path)) {
+                Some(resolved) => match entry.entry.target {
+                    Some(ref target) => (normalize(&join(&parent_real, target)), None),
+                    None => (resolved, None),
+                },
+                None => return Ok(()),
+            }
+        } else {
+            return Ok(());
+        };
+
+        self.pending = Some(Pending {
+            path: entry.path.clone(),
+            relative: entry.relative.clone(),
+            unique: unique,
+            real_path: real_path,
+            depth: entry.depth,
+        });
+        Ok(())
+    }
+}
+
+impl<'a> Iterator for Walk<'a> {
+    type Item = Result<WalkEntry>;
+
+    fn next(&mut self) -> Option<Result<WalkEntry>> {
+        if let Some(root) = self.root.take() {
+            let real_path = match self.resolve(&root) {
+                Ok(Some(real_path)) => real_path,
+                Ok(None) => root.clone(),
+                Err(err) => return Some(Err(err)),
+            };
+            let root = Pending {
+                path: root,
+                relative: String::new(),
+                real_path: real_path,
+                unique: None,
+                depth: 0,
+            };
+            if let Err(err) = self.descend(root) {
+                return Some(Err(err));
+            }
+        }
+        if let Some(dir) = self.pending.take() {
+            if let Err(err) = self.descend(dir) {
+                return Some(Err(err));
+            }
+        }
+
+        loop {
+            let next = match self.levels.last_mut() {
+                Some(level) => level.entries.next().map(|entry| WalkEntry {
+                    path: join(&level.path, &entry.name),
+                    relative: join(&level.relative, &entry.name),
+                    depth: level.depth + 1,
+                    entry: entry,
+                }),
+                None => return None,
+            };
+            match next {
+                Some(entry) => {
+                    return match self.schedule(&entry) {
+                        Ok(()) => Some(Ok(entry)),
+                        Err(err) => Some(Err(err)),
+                    };
+                }
+                None => {
+                    self.levels.pop();
+                }
+            }
+        }
+    }
+}
+
+/// Lists the local tree under `root` the way `Walk` lists a remote one, depth first
+///
+/// The paths use `/` as separator. Symbolic links are followed to files, links to
+/// directories are yielded as `EntryType::Symlink` without descending into them.
+pub fn walk_local<P: AsRef<Path>>(root: P) -> stdio::Result<Vec<WalkEntry>> {
+    let root = root.as_ref().to_string_lossy().into_owned();
+    let mut entries = Vec::new();
+    try!(walk_local_dir(&root, "", 1, &mut entries));
+    Ok(entries)
+}
+
+fn walk_local_dir(path: &str, relative: &str, depth: usize, entries: &mut Vec<WalkEntry>) -> stdio::Result<()> {
+    let mut children: Vec<fs::DirEntry> = try!(try!(fs::read_dir(path)).collect());
+    children.sort_by_key(|child| child.file_name());
+
+    for child in children {
+        let name = child.file_name().to_string_lossy().into_owned();
+        let link = try!(child.file_type()).is_symlink();
+        // a dangling link has no metadata to follow
+        let meta = match fs::metadata(child.path()) {
+            Ok(meta) => meta,
+            Err(_) => try!(fs::symlink_metadata(child.path())),
+        };
+
+        let entry_type = if meta.is_dir() && link {
+            EntryType::Symlink
+        } else if meta.is_dir() {
+            EntryType::Dir
+        } else if meta.is_file() {
+            EntryType::File
+        } else {
+            EntryType::Other
+        };
+        let mut entry = Entry::new(name, entry_type);
+        if entry_type == EntryType::File {
+            entry.size = Some(meta.len());
+        }
+        entry.modified = meta.modified().ok().map(to_utc);
+        if link {
+            entry.target = fs::read_link(child.path()).ok().map(|target| target.to_string_lossy().into_owned());
+        }
+
+        let item = WalkEntry {
+            path: join(path, &entry.name),
+            relative: join(relative, &entry.name),
+            depth: depth,
+            entry: entry,
+        };
+        let descend = item.entry.is_dir();
+        let (child_path, child_relative) = (item.path.clone(), item.relative.clone());
+        entries.push(item);
+        if descend {
+            try!(walk_local_dir(&child_path, &child_relative, depth + 1, entries));
+        }
+    }
+    Ok(())
+}
+
+/// The local path of `relative`, a path relative to a walked remote directory, under `root`
+///
+/// None unless `relative` is made of plain names only, so that what a server lists can't
+/// lead out of `root`.
+pub fn local_path(root: &str, relative: &str) -> Option<PathBuf> {
+    let plain = Path::new(relative).components().all(|part| match part {
+        Component::Normal(name) => !name.to_string_lossy().contains('\\'),
+        _ => false,
+    });
+    if plain && !relative.is_empty() {
+        Some(Path::new(root).join(relative))
+    } else {
+        None
+    }
+}
+
+/// Joins remote path components, leaving out a leading `.`
+pub fn join(parent: &str, name: &str) -> String {
+    match parent {
+        _ if name.starts_with('/') => name.to_string(),
+        "" | "." => name.to_string(),
+        _ if parent.ends_with('/') => format!("{}{}", parent, name),
+        _ => format!("{}/{}", parent, name),
+    }
+}
+
+/// Removes the `.` and `..` components of an absolute remote path
+pub(crate) fn normalize(path: &str) -> String {
+    let mut parts: Vec<&str> = Vec::new();
+    for part in path.split('/') {
+        match part {
+            "" | "." => (),
+            ".." => {
+                parts.pop();
+            }
+            _ => parts.push(part),
+        }
+    }
+    format!("/{}", parts.join("/"))
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,96 @@
+use std::env;
+use std::fs;
+use std::io as stdio;
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::path::PathBuf;
+use chrono::{DateTime, UTC};
+use super::{join, normalize, local_path, walk_local};
+use super::super::listing::{Entry, EntryType};
+use super::super::mirror::Mirror;
+use super::super::mock::{MemoryFs, MockServer, USER, PASSWORD};
+use super::super::server::{Filesystem, Server};
+
+#[test]
+fn paths() {
+    assert_eq!(join("pub", "notes.txt"), "pub/notes.txt");
+    assert_eq!(join("/", "pub"), "/pub");
+    assert_eq!(join(".", "pub"), "pub");
+    assert_eq!(join("pub", "/srv/other"), "/srv/other");
+    assert_eq!(normalize("/pub/./a/../b/"), "/pub/b");
+    assert_eq!(normalize("/../.."), "/");
+}
+
+#[test]
+fn local() {
+    let dir = env::temp_dir().join(format!("zftp-walk-{}", ::std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("b/c")).unwrap();
+    fs::write(dir.join("a.txt"), b"abc").unwrap();
+    fs::write(dir.join("b/c/d.txt"), b"").unwrap();
+
+    let walked: Vec<(String, usize, Option<u64>)> = walk_local(&dir).unwrap().into_iter()
+        .map(|item| (item.relative, item.depth, item.entry.size)).collect();
+    assert_eq!(walked, vec![(String::from("a.txt"), 1, Some(3)), (String::from("b"), 1, None),
+                            (String::from("b/c"), 2, None), (String::from("b/c/d.txt"), 3, Some(0))]);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn lazy() {
+    let server = MockServer::start();
+    server.fs().add_file("pub/a/1.txt", b"").add_file("pub/b/2.txt", b"");
+    let mut ftp = server.login();
+    let relative: Vec<String> = ftp.walk("pub").take(3).map(|item| item.unwrap().relative).collect();
+    assert_eq!(relative, vec!["a", "a/1.txt", "b"]);
+    // b was never listed
+    assert_eq!(server.received().iter().filter(|command| command.starts_with("MLSD")).count(), 2);
+}
+
+/// A `MemoryFs` whose directories also list names leading out of them
+struct Hostile(MemoryFs);
+
+impl Filesystem for Hostile {
+    fn metadata(&self, path: &str) -> stdio::Result<Entry> { self.0.metadata(path) }
+    fn read_dir(&self, path: &str) -> stdio::Result<Vec<Entry>> {
+        let mut entries = try!(self.0.read_dir(path));
+        for name in &["..", "../../.bashrc", "/etc/cron.d", "..\\evil"] {
+            let mut entry = Entry::new(name.to_string(), EntryType::File);
+            entry.size = Some(1);
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
+    fn open(&self, path: &str) -> stdio::Result<Box<dyn Read + Send>> { self.0.open(path) }
+    fn create(&self, path: &str, append: bool) -> stdio::Result<Box<dyn Write + Send>> { self.0.create(path, append) }
+    fn create_dir(&self, path: &str) -> stdio::Result<()> { self.0.create_dir(path) }
+    fn remove_dir(&self, path: &str) -> stdio::Result<()> { self.0.remove_dir(path) }
+    fn remove_file(&self, path: &str) -> stdio::Result<()> { self.0.remove_file(path) }
+    fn rename(&self, from: &str, to: &str) -> stdio::Result<()> { self.0.rename(from, to) }
+    fn set_modified(&self, path: &str, time: &DateTime<UTC>) -> stdio::Result<()> { self.0.set_modified(path, time) }
+    fn set_mode(&self, path: &str, mode: u32) -> stdio::Result<()> { self.0.set_mode(path, mode) }
+}
+
+#[test]
+fn hostile_names() {
+    let fs = MemoryFs::new();
+    fs.add_file("pub/a/1.txt", b"");
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    Server::new(Hostile(fs)).account(USER, PASSWORD).spawn(listener);
+    let mut ftp = ::FtpStream::connect_quiet(addr).unwrap();
+    ftp.login(USER, PASSWORD).unwrap();
+
+    let relative: Vec<String> = ftp.walk("pub").map(|item| item.unwrap().relative).collect();
+    assert_eq!(relative, vec!["a", "a/1.txt"]);
+    let dir = env::temp_dir().join(format!("zftp-walk-hostile-{}", ::std::process::id()));
+    let plan = Mirror::download("pub", dir.to_str().unwrap()).delete(true).plan(&mut ftp).unwrap();
+    // making the root and a, and getting a/1.txt
+    assert_eq!(plan.actions.len(), 3);
+    assert!(plan.jobs().iter().all(|job| job.local().starts_with(dir.to_str().unwrap()) && !job.local().contains("..")));
+
+    assert_eq!(local_path("/tmp/d", "a/1.txt"), Some(PathBuf::from("/tmp/d/a/1.txt")));
+    for relative in &["", "..", "a/../../x", "/etc/x", "./a", "a\\..\\x"] {
+        assert_eq!(local_path("/tmp/d", relative), None);
+    }
+}
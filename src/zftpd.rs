@@ -0,0 +1,71 @@
+extern crate FTPCLI;
+extern crate getopts;
+
+use std::env;
+use std::net::TcpListener;
+use std::path::Path;
+use std::process::exit;
+use getopts::Options;
+
+use FTPCLI::server::{Server, LocalFs};
+
+fn main() {
+    let mut opts = Options::new();
+    opts.optopt("l", "listen", "address to listen on, 127.0.0.1:2121 by default", "ADDR");
+    opts.optmulti("u", "user", "let USER log in with PASSWORD, may be repeated", "USER:PASSWORD");
+    opts.optflag("a", "anonymous", "let anonymous and ftp log in with any password");
+    opts.optflag("r", "read-only", "refuse uploads, deletions and renames");
+    opts.optflag("", "fxp", "let PORT name another host than the client's");
+    opts.optflag("h", "help", "print this help");
+
+    let args: Vec<String> = env::args().collect();
+    let matches = match opts.parse(&args[1..]) {
+        Ok(matches) => matches,
+        Err(err) => {
+            println!("{}", err);
+            exit(-1);
+        }
+    };
+    if matches.opt_present("help") || matches.free.len() != 1 {
+        print!("{}", opts.usage("Usage: zftpd [options] <DIR>"));
+        exit(if matches.opt_present("help") { 0 } else { -1 });
+    }
+
+    let root = &matches.free[0];
+    if !Path::new(root).is_dir() {
+        println!("{} is not a directory", root);
+        exit(-1);
+    }
+    let mut server = Server::new(LocalFs::new(root.clone()))
+        .anonymous(matches.opt_present("anonymous"))
+        .read_only(matches.opt_present("read-only"))
+        .fxp(matches.opt_present("fxp"));
+    let users = matches.opt_strs("user");
+    for user in &users {
+        match user.find(':') {
+            Some(colon) => server = server.account(&user[..colon], &user[colon + 1..]),
+            None => {
+                println!("Invalid user {}, expected USER:PASSWORD", user);
+                exit(-1);
+            }
+        }
+    }
+    if users.is_empty() && !matches.opt_present("anonymous") {
+        println!("Nobody could log in, use --user or --anonymous");
+        exit(-1);
+    }
+
+    let addr = matches.opt_str("listen").unwrap_or_else(|| "127.0.0.1:2121".to_string());
+    let listener = match TcpListener::bind(&addr[..]) {
+        Ok(listener) => listener,
+        Err(err) => {
+            println!("Could not listen on {}: {}", addr, err);
+            exit(-1);
+        }
+    };
+    println!("Serving {} on {}", root, addr);
+    if let Err(err) = server.serve(listener) {
+        println!("Server stopped: {}", err);
+        exit(-1);
+    }
+}